@@ -14,11 +14,88 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 
-use github_rs::client::{Executor, Github};
-use serde_json::Value;
+use std::convert::{TryFrom, TryInto};
+
+use hyper::{Body, Method, Request, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
 use thiserror::Error;
+use url::form_urlencoded;
+
+use crate::github::{
+    Nodes,
+    PullRequest,
+    ReviewComment,
+    ReviewThread,
+    ReviewThreads,
+    ThreadComment,
+};
+use crate::http::Http;
+use crate::suggestion::{self, Suggestion};
+
+
+/// The GitHub API URL.
+const API_URL: &str = "https://api.github.com/";
+
+/// Number of items to request per page.
+const PAGE_SIZE: usize = 100;
+
+/// GraphQL query for a page of the review threads of a pull request, with
+/// their comments.
+const REVIEW_THREADS_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $number: Int!, $cursor: String) {
+  repository(owner: $owner, name: $repo) {
+    pullRequest(number: $number) {
+      reviewThreads(first: 100, after: $cursor) {
+        pageInfo { hasNextPage endCursor }
+        nodes {
+          id
+          path
+          originalStartLine
+          originalLine
+          diffSide
+          isResolved
+          isOutdated
+          comments(first: 100) {
+            pageInfo { hasNextPage endCursor }
+            nodes {
+              databaseId
+              body
+              author { login ... on User { databaseId } }
+              createdAt
+              url
+              originalCommit { oid }
+              pullRequestReview { databaseId }
+            }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
 
-use crate::suggestion::Suggestion;
+/// GraphQL query for the next 100 comments in a review thread.
+const THREAD_COMMENTS_QUERY: &str = r#"
+query($id: ID!, $cursor: String) {
+  node(id: $id) {
+    ... on PullRequestReviewThread {
+      comments(first: 100, after: $cursor) {
+        pageInfo { hasNextPage endCursor }
+        nodes {
+          databaseId
+          body
+          author { login ... on User { databaseId } }
+          createdAt
+          url
+          originalCommit { oid }
+          pullRequestReview { databaseId }
+        }
+      }
+    }
+  }
+}
+"#;
 
 
 /// Client and network errors.
@@ -27,52 +104,378 @@ pub enum Error {
     #[error("GitHub client error: {0}")]
     Github(String),
 
+    #[error("Gitea client error: {0}")]
+    Gitea(String),
+
+    /// The requested repository, pull request or comment doesn't exist.
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Unable to parse URL: {0}")]
+    Url(#[from] url::ParseError),
+
     #[error("Unable to deserialize: {0}")]
     Deserialize(#[from] serde_json::error::Error),
 }
 
 
+/// A client that fetches suggestion comments from a forge's API.
+pub trait Fetch: Send + Sync {
+    /// Fetch a suggestion comment by its ID.
+    fn fetch(&self, id: &str) -> Result<Suggestion, Error>;
+}
+
+
 /// A GitHub client wrapper for a specific repository.
-pub struct Client<'a> {
-    client: Github,
-    owner: &'a str,
-    repo: &'a str,
+///
+/// Requests share one connection pool and event loop, so that `Client` can be
+/// shared between threads and reuses its connections.
+pub struct Client {
+    http: Http,
+    token: String,
+    owner: String,
+    repo: String,
 }
 
-impl<'a> Client<'a> {
+impl Client {
     /// Create a new GitHub client.
     pub fn new(
         token: &str,
-        owner: &'a str, repo: &'a str,
+        owner: &str, repo: &str,
     ) -> Result<Self, Error> {
-        let client = match Github::new(&token) {
-            Ok(g) => g,
-            Err(e) => return Err(Error::Github(e.to_string())),
+        Ok(Client {
+            http: Http::new().map_err(|e| Error::Github(e.to_string()))?,
+            token: token.to_owned(),
+            owner: owner.to_owned(),
+            repo: repo.to_owned(),
+        })
+    }
+
+    /// Fetch all suggestion comments on pull request number `pull`, with
+    /// whether their threads were resolved or outdated.
+    ///
+    /// Review threads are fetched from the GraphQL API, 100 at a time along
+    /// with their first 100 comments. Threads with more comments are
+    /// followed up with a query for each further page.
+    pub fn list(&self, pull: &str) -> Result<Vec<Suggestion>, Error> {
+        let number: u64 = pull.parse()
+            .map_err(|_| Error::Github(
+                format!("invalid pull request number '{}'", pull),
+            ))?;
+
+        let mut suggestions = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let mut data = self.graphql(
+                REVIEW_THREADS_QUERY,
+                json!({
+                    "owner": self.owner,
+                    "repo": self.repo,
+                    "number": number,
+                    "cursor": cursor,
+                }),
+            )?;
+
+            let threads = data["repository"]["pullRequest"]["reviewThreads"]
+                .take();
+
+            if threads.is_null() {
+                return Err(Error::NotFound(
+                    format!(
+                        "pull request {}/{}#{}",
+                        self.owner,
+                        self.repo,
+                        pull,
+                    ),
+                ));
+            }
+
+            let threads: ReviewThreads = serde_json::from_value(threads)?;
+
+            for mut thread in threads.nodes {
+                self.fetch_remaining_comments(&mut thread)?;
+
+                suggestions.extend(thread.into_suggestions());
+            }
+
+            if !threads.page_info.has_next_page {
+                break;
+            }
+
+            cursor = threads.page_info.end_cursor;
+        }
+
+        Ok(suggestions)
+    }
+
+    /// Fetch the comments of `thread` after its first page, and add them to
+    /// it.
+    fn fetch_remaining_comments(
+        &self,
+        thread: &mut ReviewThread,
+    ) -> Result<(), Error> {
+        while thread.comments.page_info.has_next_page {
+            let mut data = self.graphql(
+                THREAD_COMMENTS_QUERY,
+                json!({
+                    "id": thread.id,
+                    "cursor": thread.comments.page_info.end_cursor,
+                }),
+            )?;
+
+            let page: Nodes<ThreadComment> =
+                serde_json::from_value(data["node"]["comments"].take())?;
+
+            thread.comments.nodes.extend(page.nodes);
+            thread.comments.page_info = page.page_info;
+        }
+
+        Ok(())
+    }
+
+    /// Fetch pull request number `pull`.
+    pub fn pull_request(&self, pull: &str) -> Result<PullRequest, Error> {
+        self.get(
+            &format!("repos/{}/{}/pulls/{}", self.owner, self.repo, pull),
+        )
+    }
+
+    /// Find the number of the open pull request whose head is `head`, given
+    /// as `owner:branch`.
+    pub fn pull_for_branch(&self, head: &str) -> Result<Option<u64>, Error> {
+        let pulls: Vec<PullRequest> = self.get(
+            &format!(
+                "repos/{}/{}/pulls?{}",
+                self.owner,
+                self.repo,
+                open_pulls_query(head),
+            ),
+        )?;
+
+        Ok(pulls.first().map(|p| p.number))
+    }
+
+    /// Fetch the suggestion comments of review `review_id` on pull request
+    /// number `pull`, sorted by file and line.
+    pub fn review(
+        &self,
+        pull: &str,
+        review_id: &str,
+    ) -> Result<Vec<Suggestion>, Error> {
+        let comments = self.suggestion_comments(
+            &format!(
+                "repos/{}/{}/pulls/{}/reviews/{}/comments",
+                self.owner,
+                self.repo,
+                pull,
+                review_id,
+            ),
+        )?;
+
+        let mut suggestions: Vec<Suggestion> = comments.into_iter()
+            .filter_map(|c| Suggestion::try_from(c).ok())
+            .collect();
+
+        suggestions.sort_by(|a, b| {
+            (&a.path, a.start_line).cmp(&(&b.path, b.start_line))
+        });
+
+        Ok(suggestions)
+    }
+
+    /// Fetch all pages of the review comment list `endpoint`, and keep the
+    /// comments that contain a suggestion.
+    fn suggestion_comments(
+        &self,
+        endpoint: &str,
+    ) -> Result<Vec<ReviewComment>, Error> {
+        let mut comments = Vec::new();
+
+        for page in 1.. {
+            let page_comments: Vec<ReviewComment> = self.get(
+                &format!("{}?per_page={}&page={}", endpoint, PAGE_SIZE, page),
+            )?;
+
+            let is_last_page = page_comments.len() < PAGE_SIZE;
+
+            comments.extend(
+                page_comments.into_iter()
+                    .filter(|c| suggestion::has_suggestion(&c.body)),
+            );
+
+            if is_last_page {
+                break;
+            }
+        }
+
+        Ok(comments)
+    }
+
+    /// Send a `method` request to the API `endpoint` with an optional JSON
+    /// `body`, and get the response status and JSON body, if any.
+    fn request(
+        &self,
+        method: Method,
+        endpoint: &str,
+        body: Option<Value>,
+    ) -> Result<(StatusCode, Option<Value>), Error> {
+        let body = match body {
+            Some(body) => Body::from(body.to_string()),
+            None => Body::empty(),
         };
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", API_URL, endpoint))
+            .header("Accept", "application/vnd.github.v3+json")
+            .header("Authorization", format!("token {}", self.token))
+            .header("User-Agent", "git-suggestion")
+            .body(body)
+            .map_err(|e| Error::Github(e.to_string()))?;
 
-        Ok(Client { client, owner, repo })
+        let (status, body) = self.http.send(request)
+            .map_err(|e| Error::Github(e.to_string()))?;
+
+        Ok((status, serde_json::from_slice(&body).ok()))
+    }
+
+    /// Request the REST API `endpoint` and deserialize the response.
+    fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, Error> {
+        match self.request(Method::GET, endpoint, None)? {
+            (status, Some(json)) if status.is_success() =>
+                Ok(serde_json::from_value(json)?),
+            (StatusCode::NOT_FOUND, _) =>
+                Err(Error::NotFound(endpoint.to_owned())),
+            (status, _) =>
+                Err(Error::Github(format!("{}: {}", endpoint, status))),
+        }
     }
 
+    /// Run a GraphQL `query` with `variables`, and get the response data.
+    fn graphql(&self, query: &str, variables: Value) -> Result<Value, Error> {
+        let body = json!({
+            "query": query,
+            "variables": variables,
+        });
+        let (status, json) = self.request(Method::POST, "graphql", Some(body))?;
+
+        graphql_data(status, json)
+    }
+}
+
+impl Fetch for Client {
     /// Fetch a suggestion comment from GitHub by its ID.
-    pub fn fetch(&self, id: &str) -> Result<Suggestion, Error> {
-        let response = self.client
-            .get()
-            .repos()
-            .owner(self.owner)
-            .repo(self.repo)
-            .pulls()
-            .comments()
-            .id(id)
-            .execute::<Value>();
-
-        match response {
-            Ok((_, _, Some(json))) => {
-                let suggestion = serde_json::from_value(json)?;
-
-                Ok(suggestion)
+    fn fetch(&self, id: &str) -> Result<Suggestion, Error> {
+        let endpoint = format!(
+            "repos/{}/{}/pulls/comments/{}",
+            self.owner, self.repo, id,
+        );
+
+        match self.request(Method::GET, &endpoint, None)? {
+            (status, Some(json)) if status.is_success() => {
+                let comment: ReviewComment = serde_json::from_value(json)?;
+
+                comment.try_into()
+                    .map_err(|e: suggestion::Error| Error::Github(e.to_string()))
             },
-            Ok((_, _, None)) => Err(Error::Github("no response".to_owned())),
-            Err(e) => Err(Error::Github(e.to_string())),
+            (StatusCode::NOT_FOUND, _) =>
+                Err(Error::NotFound(format!("comment {}", id))),
+            (status, Some(_)) =>
+                Err(Error::Github(format!("comment {}: {}", id, status))),
+            (_, None) => Err(Error::Github("no response".to_owned())),
         }
     }
 }
+
+/// Build the query string to list the open pull requests whose head is
+/// `head`, which can contain characters that are special in URLs.
+fn open_pulls_query(head: &str) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .append_pair("state", "open")
+        .append_pair("head", head)
+        .finish()
+}
+
+/// Get the data from a GraphQL response with `status` and body `json`.
+///
+/// Error responses, such as for bad credentials or rate limits, have no
+/// GraphQL errors, so the status is checked first.
+fn graphql_data(
+    status: StatusCode,
+    json: Option<Value>,
+) -> Result<Value, Error> {
+    let mut json = match json {
+        Some(json) if status.is_success() => json,
+        Some(json) if json["message"].is_string() => return Err(Error::Github(
+            format!("graphql: {}: {}", status, json["message"]),
+        )),
+        _ => return Err(Error::Github(format!("graphql: {}", status))),
+    };
+
+    if let Some(errors) = json["errors"].as_array() {
+        let messages: Vec<_> = errors.iter()
+            .filter_map(|e| e["message"].as_str())
+            .collect();
+        let messages = messages.join("; ");
+
+        if !errors.is_empty()
+            && errors.iter().all(|e| e["type"] == "NOT_FOUND")
+        {
+            return Err(Error::NotFound(messages));
+        }
+
+        return Err(Error::Github(messages));
+    }
+
+    Ok(json["data"].take())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphql_data_fails_on_error_status() {
+        let result = graphql_data(
+            StatusCode::UNAUTHORIZED,
+            Some(json!({ "message": "Bad credentials" })),
+        );
+
+        match result {
+            Err(Error::Github(message)) =>
+                assert!(message.contains("Bad credentials")),
+            r => panic!("expected a GitHub error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn graphql_data_reports_missing_objects_as_not_found() {
+        let result = graphql_data(
+            StatusCode::OK,
+            Some(json!({
+                "data": { "repository": null },
+                "errors": [{
+                    "type": "NOT_FOUND",
+                    "message": "Could not resolve to a Repository",
+                }],
+            })),
+        );
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+
+        let data = graphql_data(
+            StatusCode::OK,
+            Some(json!({ "data": { "repository": { "id": "R_1" } } })),
+        ).unwrap();
+
+        assert_eq!(data["repository"]["id"], "R_1");
+    }
+    #[test]
+    fn open_pulls_query_encodes_head() {
+        assert_eq!(
+            open_pulls_query("teddywing:c++&fix#1"),
+            "state=open&head=teddywing%3Ac%2B%2B%26fix%231",
+        );
+    }
+}
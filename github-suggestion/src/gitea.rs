@@ -0,0 +1,402 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::sync::Mutex;
+
+use hyper::{Body, Request, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use url::Url;
+
+use crate::client::{Error, Fetch};
+use crate::http::Http;
+use crate::suggestion::{self, Author, Side, Suggestion};
+
+
+/// Number of reviews to request per page.
+const PAGE_LIMIT: usize = 50;
+
+/// A review on a pull request.
+#[derive(Debug, Deserialize)]
+struct Review {
+    id: u64,
+}
+
+/// The user who wrote a review comment.
+#[derive(Clone, Debug, Deserialize)]
+struct User {
+    login: String,
+
+    #[serde(default)]
+    email: String,
+}
+
+/// A review comment in the format returned by the Gitea API.
+#[derive(Clone, Debug, Deserialize)]
+struct ReviewComment {
+    id: u64,
+    pull_request_review_id: Option<u64>,
+    body: String,
+    user: User,
+    path: String,
+    commit_id: String,
+    original_commit_id: String,
+    position: usize,
+    original_position: usize,
+    created_at: String,
+    html_url: Option<String>,
+    resolver: Option<User>,
+}
+
+/// Gitea comments are on a single line. Comments on the new side of the diff
+/// have a `position`, and comments on the old side only have an
+/// `original_position`.
+impl From<ReviewComment> for Suggestion {
+    fn from(comment: ReviewComment) -> Self {
+        let (commit, line, side) = if comment.position != 0 {
+            (comment.commit_id, comment.position, Side::Right)
+        } else {
+            (comment.original_commit_id, comment.original_position, Side::Left)
+        };
+
+        let (message, replacement) = suggestion::parse_comment(&comment.body);
+
+        Suggestion {
+            id: comment.id.to_string(),
+            review_id: comment.pull_request_review_id
+                .map(|id| id.to_string()),
+            path: comment.path,
+            commit,
+            start_line: line,
+            end_line: line,
+            side,
+            message,
+            replacement,
+            author: Author {
+                name: comment.user.login,
+                email: comment.user.email,
+            },
+            created_at: comment.created_at,
+            url: comment.html_url,
+            outdated: false,
+            resolved: Some(comment.resolver.is_some()),
+        }
+    }
+}
+
+
+/// A Gitea or Forgejo client wrapper for a specific pull request.
+///
+/// Gitea can only list review comments by review, so comments are looked up
+/// in the reviews of the pull request. They are listed on the first fetch
+/// and kept for later ones. Requests share one connection pool and event loop,
+/// so that `Client` can be shared between threads.
+pub struct Client {
+    http: Http,
+    api_url: Url,
+    token: String,
+    pull: String,
+    comments: Mutex<Option<Vec<ReviewComment>>>,
+}
+
+impl Client {
+    /// Create a new client for the server at `base_url`, for example
+    /// `https://codeberg.org`.
+    pub fn new(
+        base_url: &str,
+        token: &str,
+        owner: &str, repo: &str,
+        pull: &str,
+    ) -> Result<Self, Error> {
+        let api_url = with_trailing_slash(Url::parse(base_url)?)
+            .join("api/v1/")?;
+
+        Self::with_api_url(api_url.as_str(), token, owner, repo, pull)
+    }
+
+    /// Create a new client for the server whose API is at `api_url`, for
+    /// example `https://codeberg.org/api/v1`.
+    pub fn with_api_url(
+        api_url: &str,
+        token: &str,
+        owner: &str, repo: &str,
+        pull: &str,
+    ) -> Result<Self, Error> {
+        let api_url = with_trailing_slash(Url::parse(api_url)?)
+            .join(&format!("repos/{}/{}/", owner, repo))?;
+
+        Ok(Client {
+            http: Http::new().map_err(|e| Error::Gitea(e.to_string()))?,
+            api_url,
+            token: token.to_owned(),
+            pull: pull.to_owned(),
+            comments: Mutex::new(None),
+        })
+    }
+
+    /// List the review comments in all reviews on the pull request.
+    fn list_comments(&self) -> Result<Vec<ReviewComment>, Error> {
+        let mut comments = Vec::new();
+
+        for page in 1.. {
+            let reviews: Vec<Review> = self.get(
+                &format!(
+                    "pulls/{}/reviews?page={}&limit={}",
+                    self.pull,
+                    page,
+                    PAGE_LIMIT,
+                ),
+            )?;
+
+            if reviews.is_empty() {
+                break;
+            }
+
+            for review in reviews {
+                let review_comments: Vec<ReviewComment> = self.get(
+                    &format!("pulls/{}/reviews/{}/comments", self.pull, review.id),
+                )?;
+
+                comments.extend(review_comments);
+            }
+        }
+
+        Ok(comments)
+    }
+
+    /// Request `path` relative to the repository's API URL and deserialize
+    /// the JSON response.
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let url = self.api_url.join(path)?;
+
+        let request = Request::get(url.as_str())
+            .header("Accept", "application/json")
+            .header("Authorization", format!("token {}", self.token))
+            .header("User-Agent", "git-suggestion")
+            .body(Body::empty())
+            .map_err(|e| Error::Gitea(e.to_string()))?;
+
+        let (status, body) = self.http.send(request)
+            .map_err(|e| Error::Gitea(e.to_string()))?;
+
+        if status == StatusCode::NOT_FOUND {
+            return Err(Error::NotFound(url.to_string()));
+        }
+
+        if !status.is_success() {
+            return Err(Error::Gitea(format!("{}: {}", url, status)));
+        }
+
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+impl Fetch for Client {
+    /// Fetch a suggestion comment from the pull request by its ID.
+    fn fetch(&self, id: &str) -> Result<Suggestion, Error> {
+        let mut comments = self.comments.lock()
+            .expect("comments lock is not poisoned");
+
+        if comments.is_none() {
+            *comments = Some(self.list_comments()?);
+        }
+
+        let comment = comments.as_ref()
+            .expect("comments were listed")
+            .iter()
+            .find(|c| c.id.to_string() == id);
+
+        if let Some(c) = comment {
+            if c.position == 0 && c.original_position == 0 {
+                return Err(
+                    Error::Gitea(format!("comment {} has no line", id)),
+                );
+            }
+
+            return Ok(c.clone().into());
+        }
+
+        Err(
+            Error::NotFound(
+                format!("comment {} in pull request {}", id, self.pull),
+            )
+        )
+    }
+}
+
+/// Add a `/` to the end of the path of `url` if it doesn't have one, so that
+/// relative URLs are joined onto its last segment.
+fn with_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    url
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// Serve canned JSON `responses` for request paths on a local port, and
+    /// return the server's base URL with a count of the requests it has
+    /// served. Other paths get a 404.
+    fn serve(
+        responses: Vec<(String, &'static str)>,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let served = Arc::clone(&requests);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                }
+
+                let path = request_line.split(' ').nth(1).unwrap_or("");
+                let (status, body) = match responses.iter().find(|r| r.0 == path) {
+                    Some((_, body)) => ("200 OK", *body),
+                    None => ("404 Not Found", "{}"),
+                };
+
+                served.fetch_add(1, Ordering::SeqCst);
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\n\
+                        Content-Type: application/json\r\n\
+                        Content-Length: {}\r\n\
+                        Connection: close\r\n\
+                        \r\n\
+                        {}",
+                    status,
+                    body.len(),
+                    body,
+                ).unwrap();
+            }
+        });
+
+        (format!("http://{}", address), requests)
+    }
+
+    fn server() -> (String, Arc<AtomicUsize>) {
+        let api = "/api/v1/repos/teddywing/git-suggestion/pulls/1";
+
+        serve(vec![
+            (
+                format!("{}/reviews?page=1&limit=50", api),
+                r#"[{ "id": 1 }, { "id": 2 }]"#,
+            ),
+            (format!("{}/reviews?page=2&limit=50", api), "[]"),
+            (format!("{}/reviews/1/comments", api), "[]"),
+            (
+                format!("{}/reviews/2/comments", api),
+                r#"[
+                    {
+                        "id": 11,
+                        "body": "Use the sword.\n```suggestion\nsword\n```",
+                        "user": { "id": 1, "login": "oshino-shinobu", "email": "oshino@example.com" },
+                        "path": "poems/Jabberwocky.txt",
+                        "commit_id": "89840a2",
+                        "original_commit_id": "06acdfc",
+                        "position": 7,
+                        "original_position": 0,
+                        "created_at": "2020-07-23T17:20:29Z"
+                    },
+                    {
+                        "id": 12,
+                        "body": "```suggestion\nblade\n```",
+                        "user": { "id": 1, "login": "oshino-shinobu", "email": "oshino@example.com" },
+                        "path": "poems/Jabberwocky.txt",
+                        "commit_id": "89840a2",
+                        "original_commit_id": "06acdfc",
+                        "position": 0,
+                        "original_position": 7,
+                        "created_at": "2020-07-23T17:20:29Z"
+                    }
+                ]"#,
+            ),
+        ])
+    }
+
+    #[test]
+    fn client_fetch_finds_comment_in_reviews() {
+        let client = Client::new(
+            &server().0,
+            "token",
+            "teddywing", "git-suggestion",
+            "1",
+        ).unwrap();
+
+        let suggestion = client.fetch("11").unwrap();
+        assert_eq!(suggestion.path, "poems/Jabberwocky.txt");
+        assert_eq!(suggestion.commit, "89840a2");
+        assert_eq!(suggestion.end_line, 7);
+        assert_eq!(suggestion.side, Side::Right);
+        assert_eq!(suggestion.message, "Use the sword.");
+        assert_eq!(suggestion.replacement, "sword\n");
+
+        let old_side = client.fetch("12").unwrap();
+        assert_eq!(old_side.commit, "06acdfc");
+        assert_eq!(old_side.side, Side::Left);
+
+        match client.fetch("13") {
+            Err(Error::NotFound(_)) => (),
+            r => panic!("expected comment not to be found, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn client_fetch_lists_comments_once() {
+        let (url, requests) = server();
+        let client = Client::new(
+            &url,
+            "token",
+            "teddywing", "git-suggestion",
+            "1",
+        ).unwrap();
+
+        client.fetch("11").unwrap();
+        let listed = requests.load(Ordering::SeqCst);
+        assert_eq!(listed, 4);
+
+        client.fetch("12").unwrap();
+        assert!(client.fetch("13").is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), listed);
+    }
+}
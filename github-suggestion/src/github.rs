@@ -0,0 +1,500 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::convert::{TryFrom, TryInto};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::suggestion::{self, Author, Side, Suggestion};
+
+
+/// The GitHub user who wrote a review comment.
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub login: String,
+    pub id: u64,
+}
+
+impl User {
+    /// Get the user's GitHub "noreply" email address.
+    pub fn email(&self) -> String {
+        format!("{}+{}@users.noreply.github.com", self.id, self.login)
+    }
+}
+
+/// A pull request review comment in the format returned by the GitHub API.
+#[derive(Debug, Deserialize)]
+pub struct ReviewComment {
+    pub id: u64,
+    pub pull_request_review_id: Option<u64>,
+    pub body: String,
+    pub original_commit_id: String,
+    pub path: String,
+    pub original_start_line: Option<usize>,
+
+    /// `None` for comments that aren't on a line, like comments on a whole
+    /// file.
+    pub original_line: Option<usize>,
+
+    pub position: Option<usize>,
+    pub side: Option<String>,
+    pub user: User,
+    pub created_at: String,
+    pub html_url: Option<String>,
+}
+
+impl TryFrom<ReviewComment> for Suggestion {
+    type Error = suggestion::Error;
+
+    fn try_from(comment: ReviewComment) -> Result<Self, Self::Error> {
+        let line = match comment.original_line {
+            Some(line) => line,
+            None => {
+                return Err(suggestion::Error::NoLine(comment.id.to_string()));
+            },
+        };
+
+        let start_line = comment.original_start_line.unwrap_or(line);
+
+        if start_line > line {
+            return Err(suggestion::Error::LineRange(comment.id.to_string()));
+        }
+
+        let (message, replacement) = suggestion::parse_comment(&comment.body);
+
+        Ok(Suggestion {
+            id: comment.id.to_string(),
+            review_id: comment.pull_request_review_id
+                .map(|id| id.to_string()),
+            path: comment.path,
+            commit: comment.original_commit_id,
+            start_line,
+            end_line: line,
+            side: match comment.side.as_deref() {
+                Some("LEFT") => Side::Left,
+                _ => Side::Right,
+            },
+            message,
+            replacement,
+            author: Author {
+                email: comment.user.email(),
+                name: comment.user.login,
+            },
+            created_at: comment.created_at,
+            url: comment.html_url,
+
+            // GitHub only returns a `position` for comments on lines that
+            // are still in the pull request diff.
+            outdated: comment.position.is_none(),
+
+            // Resolution is only available from the GraphQL API.
+            resolved: None,
+        })
+    }
+}
+
+/// A pull request in the format returned by the GitHub API.
+#[derive(Debug, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub head: Branch,
+    pub base: Branch,
+
+    /// Not included when listing pull requests.
+    #[serde(default)]
+    pub maintainer_can_modify: bool,
+}
+
+/// The head or base branch of a pull request.
+#[derive(Debug, Deserialize)]
+pub struct Branch {
+    #[serde(rename = "ref")]
+    pub name: String,
+
+    /// The repository of the branch, or `None` if it was deleted.
+    pub repo: Option<Repository>,
+}
+
+/// A GitHub repository.
+#[derive(Debug, Deserialize)]
+pub struct Repository {
+    pub full_name: String,
+    pub clone_url: String,
+}
+
+/// A page of pull request review threads in the format returned by the
+/// GitHub GraphQL API.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewThreads {
+    pub page_info: PageInfo,
+    pub nodes: Vec<ReviewThread>,
+}
+
+/// GraphQL pagination information.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub end_cursor: Option<String>,
+}
+
+/// A review thread, the comments on a range of lines, in the format returned
+/// by the GitHub GraphQL API.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewThread {
+    pub id: String,
+    pub path: String,
+    pub original_start_line: Option<usize>,
+    pub original_line: Option<usize>,
+    pub diff_side: String,
+    pub is_resolved: bool,
+    pub is_outdated: bool,
+    pub comments: Nodes<ThreadComment>,
+}
+
+/// A page of a GraphQL connection's nodes.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Nodes<T> {
+    pub page_info: PageInfo,
+    pub nodes: Vec<T>,
+}
+
+/// A comment in a review thread.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadComment {
+    pub database_id: u64,
+    pub body: String,
+    pub author: Option<Actor>,
+    pub created_at: String,
+    pub url: Option<String>,
+    pub original_commit: Option<Commit>,
+    pub pull_request_review: Option<Review>,
+}
+
+/// The author of a comment. `database_id` is only set for users.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Actor {
+    pub login: String,
+    pub database_id: Option<u64>,
+}
+
+/// A commit, identified by its object ID.
+#[derive(Debug, Deserialize)]
+pub struct Commit {
+    pub oid: String,
+}
+
+/// The review that a comment was submitted in.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Review {
+    pub database_id: Option<u64>,
+}
+
+impl ReviewThread {
+    /// Convert the thread's suggestion comments to suggestions.
+    ///
+    /// Threads without an original line or that end before they start, and
+    /// comments without an original commit, can't be located and are
+    /// skipped.
+    pub fn into_suggestions(self) -> Vec<Suggestion> {
+        let end_line = match self.original_line {
+            Some(line) => line,
+            None => return Vec::new(),
+        };
+        let start_line = self.original_start_line.unwrap_or(end_line);
+
+        if start_line > end_line {
+            return Vec::new();
+        }
+
+        let side = match self.diff_side.as_str() {
+            "LEFT" => Side::Left,
+            _ => Side::Right,
+        };
+
+        let path = self.path;
+        let outdated = self.is_outdated;
+        let resolved = self.is_resolved;
+
+        self.comments.nodes
+            .into_iter()
+            .filter(|c| suggestion::has_suggestion(&c.body))
+            .filter_map(|comment| {
+                let commit = comment.original_commit?.oid;
+                let (message, replacement) =
+                    suggestion::parse_comment(&comment.body);
+
+                let author = match comment.author {
+                    Some(Actor { login, database_id: Some(id) }) => {
+                        let user = User { login, id };
+
+                        Author {
+                            email: user.email(),
+                            name: user.login,
+                        }
+                    },
+                    Some(Actor { login, database_id: None }) => Author {
+                        name: login,
+                        email: String::new(),
+                    },
+                    None => Author {
+                        name: "ghost".to_owned(),
+                        email: String::new(),
+                    },
+                };
+
+                Some(Suggestion {
+                    id: comment.database_id.to_string(),
+                    review_id: comment.pull_request_review
+                        .and_then(|r| r.database_id)
+                        .map(|id| id.to_string()),
+                    path: path.clone(),
+                    commit,
+                    start_line,
+                    end_line,
+                    side,
+                    message,
+                    replacement,
+                    author,
+                    created_at: comment.created_at,
+                    url: comment.url,
+                    outdated,
+                    resolved: Some(resolved),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Deserialize a suggestion from a review comment JSON object in the format
+/// returned by the GitHub API.
+pub fn from_json(json: &str) -> Result<Suggestion, suggestion::Error> {
+    let comment: ReviewComment = serde_json::from_str(json)?;
+
+    comment.try_into()
+}
+
+/// Deserialize suggestions from `json`, which can be a single review comment
+/// object, an array of comments, or newline-delimited comment objects.
+/// Comments without a suggestion block, or that aren't on a range of lines,
+/// are skipped.
+pub fn list_from_json(json: &str) -> Result<Vec<Suggestion>, suggestion::Error> {
+    let mut comments: Vec<ReviewComment> = Vec::new();
+
+    for value in serde_json::Deserializer::from_str(json).into_iter() {
+        match value? {
+            Value::Array(values) => {
+                for v in values {
+                    comments.push(serde_json::from_value(v)?);
+                }
+            },
+            v => comments.push(serde_json::from_value(v)?),
+        }
+    }
+
+    Ok(
+        comments.into_iter()
+            .filter(|c| suggestion::has_suggestion(&c.body))
+            .filter_map(|c| Suggestion::try_from(c).ok())
+            .collect()
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_json_extracts_message_and_replacement() {
+        let suggestion = from_json(r#"{
+            "id": 459691747,
+            "body": "Use the sword.\n\nThe blade is too dull.\n``` suggestion\r\n     He took his vorpal sword in hand:\r\n```",
+            "original_commit_id": "89840a2",
+            "path": "poems/Jabberwocky.txt",
+            "original_start_line": null,
+            "original_line": 7,
+            "position": null,
+            "side": "RIGHT",
+            "user": { "login": "oshino-shinobu", "id": 1 },
+            "created_at": "2020-07-23T17:20:29Z",
+            "html_url": "https://github.com/teddywing/git-suggestion/pull/1#discussion_r459691747"
+        }"#).unwrap();
+
+        assert_eq!(suggestion.message, "Use the sword.\n\nThe blade is too dull.");
+        assert_eq!(
+            suggestion.replacement,
+            "     He took his vorpal sword in hand:\n",
+        );
+        assert_eq!(suggestion.start_line, 7);
+        assert_eq!(suggestion.end_line, 7);
+        assert_eq!(suggestion.side, Side::Right);
+        assert!(suggestion.outdated);
+        assert_eq!(
+            suggestion.author,
+            Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+        );
+    }
+
+    #[test]
+    fn from_json_rejects_comment_that_ends_before_it_starts() {
+        let result = from_json(r#"{
+            "id": 459691747,
+            "body": "``` suggestion\r\n     He took his vorpal sword in hand:\r\n```",
+            "original_commit_id": "89840a2",
+            "path": "poems/Jabberwocky.txt",
+            "original_start_line": 8,
+            "original_line": 7,
+            "position": null,
+            "side": "RIGHT",
+            "user": { "login": "oshino-shinobu", "id": 1 },
+            "created_at": "2020-07-23T17:20:29Z",
+            "html_url": null
+        }"#);
+
+        match result {
+            Err(suggestion::Error::LineRange(id)) => assert_eq!(id, "459691747"),
+            r => panic!("expected a line range error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn list_from_json_skips_comments_without_a_line() {
+        let comment = |id, line| format!(
+            r#"{{
+                "id": {},
+                "body": "```suggestion\nHe took his vorpal sword in hand:\n```",
+                "original_commit_id": "89840a2",
+                "path": "poems/Jabberwocky.txt",
+                "original_start_line": null,
+                "original_line": {},
+                "position": null,
+                "side": "RIGHT",
+                "user": {{ "login": "oshino-shinobu", "id": 1 }},
+                "created_at": "2020-07-23T17:20:29Z"
+            }}"#,
+            id,
+            line,
+        );
+
+        let json = format!("[{}, {}]", comment(1, "7"), comment(2, "null"));
+        let suggestions = list_from_json(&json).unwrap();
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].id, "1");
+
+        assert!(matches!(
+            from_json(&comment(2, "null")),
+            Err(suggestion::Error::NoLine(id)) if id == "2",
+        ));
+    }
+
+    #[test]
+    fn list_from_json_skips_comments_without_a_suggestion() {
+        let comment = |id, body| format!(
+            r#"{{
+                "id": {},
+                "body": "{}",
+                "original_commit_id": "89840a2",
+                "path": "poems/Jabberwocky.txt",
+                "original_start_line": null,
+                "original_line": 7,
+                "position": null,
+                "side": "RIGHT",
+                "user": {{ "login": "oshino-shinobu", "id": 1 }},
+                "created_at": "2020-07-23T17:20:29Z"
+            }}"#,
+            id,
+            body,
+        );
+
+        let suggestion = "```suggestion\\nHe took his vorpal sword in hand:\\n```";
+        let json = format!(
+            "[{}, {}, {}]",
+            comment(1, "Nit: rename this"),
+            comment(2, suggestion),
+            comment(3, "```rust\\nlet sword = vorpal;\\n```"),
+        );
+        let suggestions = list_from_json(&json).unwrap();
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].id, "2");
+        assert_eq!(
+            suggestions[0].replacement,
+            "He took his vorpal sword in hand:\n",
+        );
+    }
+
+    #[test]
+    fn review_thread_into_suggestions_keeps_suggestion_comments() {
+        let thread: ReviewThread = serde_json::from_str(r#"{
+            "id": "PRRT_kwDOEJ9qO84Abc",
+            "path": "poems/Jabberwocky.txt",
+            "originalStartLine": 6,
+            "originalLine": 7,
+            "diffSide": "RIGHT",
+            "isResolved": true,
+            "isOutdated": false,
+            "comments": {
+                "pageInfo": { "hasNextPage": false, "endCursor": null },
+                "nodes": [
+                    {
+                        "databaseId": 459691747,
+                        "body": "```suggestion\nHe took his vorpal sword in hand:\n```",
+                        "author": { "login": "oshino-shinobu", "databaseId": 1 },
+                        "createdAt": "2020-07-23T17:20:29Z",
+                        "url": "https://github.com/teddywing/git-suggestion/pull/1#discussion_r459691747",
+                        "originalCommit": { "oid": "89840a2" },
+                        "pullRequestReview": { "databaseId": 462370958 }
+                    },
+                    {
+                        "databaseId": 459691748,
+                        "body": "Done.",
+                        "author": null,
+                        "createdAt": "2020-07-24T09:00:00Z",
+                        "url": null,
+                        "originalCommit": { "oid": "89840a2" },
+                        "pullRequestReview": null
+                    }
+                ]
+            }
+        }"#).unwrap();
+
+        let suggestions = thread.into_suggestions();
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].id, "459691747");
+        assert_eq!(suggestions[0].review_id.as_deref(), Some("462370958"));
+        assert_eq!((suggestions[0].start_line, suggestions[0].end_line), (6, 7));
+        assert_eq!(suggestions[0].resolved, Some(true));
+        assert!(!suggestions[0].outdated);
+        assert_eq!(
+            suggestions[0].author.email,
+            "1+oshino-shinobu@users.noreply.github.com",
+        );
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::io;
+
+use futures::{Future, Stream};
+use futures::sync::oneshot;
+use hyper::{Body, Request, StatusCode};
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+use tokio::runtime::{self, Runtime};
+
+
+/// An HTTPS client with its own event loop, which can be shared between
+/// threads.
+///
+/// Connections are kept open and reused for later requests, from any thread.
+pub(crate) struct Http {
+    client: hyper::Client<HttpsConnector<HttpConnector>>,
+    runtime: Runtime,
+}
+
+impl Http {
+    /// Start the event loop and create the client.
+    pub fn new() -> Result<Self, io::Error> {
+        // Requests spend their time waiting on the network, so one thread
+        // can handle them all.
+        let runtime = runtime::Builder::new()
+            .core_threads(1)
+            .name_prefix("git-suggestion-http-")
+            .build()?;
+
+        let client = hyper::Client::builder()
+            .build(HttpsConnector::new(4));
+
+        Ok(Http { client, runtime })
+    }
+
+    /// Send `request` on the event loop, and wait for the status and body of
+    /// the response.
+    pub fn send(
+        &self,
+        request: Request<Body>,
+    ) -> Result<(StatusCode, Vec<u8>), hyper::Error> {
+        let work = self.client
+            .request(request)
+            .and_then(|res| {
+                let status = res.status();
+
+                res.into_body()
+                    .concat2()
+                    .map(move |body| (status, body.to_vec()))
+            });
+
+        oneshot::spawn(work, &self.runtime.executor()).wait()
+    }
+}
@@ -18,10 +18,12 @@
 
 
 pub mod client;
+pub mod gitea;
+pub mod github;
+mod http;
 pub mod suggestion;
+pub mod url;
 
-mod url;
-
-pub use crate::client::Client;
-pub use crate::suggestion::Suggestion;
+pub use crate::client::{Client, Fetch};
+pub use crate::suggestion::{Author, Side, Suggestion};
 pub use crate::url::SuggestionUrl;
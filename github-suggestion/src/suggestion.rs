@@ -14,13 +14,17 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 
+use std::borrow::Cow;
+use std::fs;
 use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
 
+use encoding_rs::{Encoding, UTF_8};
 use git2::{Patch, Repository};
-use regex::Regex;
-use serde::Deserialize;
 use thiserror::Error;
+use url::Url;
+
+use crate::github;
 
 
 #[derive(Debug, Error)]
@@ -43,218 +47,813 @@ pub enum Error {
         message: String,
     },
 
-    #[error("{0} is not valid UTF-8")]
-    InvalidUtf8(String),
+    #[error("{0} is a binary file")]
+    BinaryFile(String),
+
+    #[error("Unknown working-tree-encoding '{0}'")]
+    UnknownEncoding(String),
+
+    #[error("Suggestion for {path} cannot be encoded as {encoding}")]
+    Encode {
+        path: String,
+        encoding: &'static str,
+    },
 
     #[error("unable to convert from UTF-8: {0}")]
     FromUtf8(#[from] std::str::Utf8Error),
 
-    #[error("Regex error: {0}")]
-    Regex(#[from] regex::Error),
+    #[error("Unable to deserialize: {0}")]
+    Deserialize(#[from] serde_json::Error),
+
+    #[error("Comment {0} is not on a line")]
+    NoLine(String),
+
+    #[error("Comment {0} ends before the line it starts on")]
+    LineRange(String),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum LineEnding {
     Lf,
     CrLf,
 }
 
-/// A suggestion comment extracted from the GitHub API.
-#[derive(Debug, Deserialize)]
+impl LineEnding {
+    /// Get the line ending that terminates `line`, if any.
+    fn of(line: &[u8]) -> Option<Self> {
+        if line.ends_with(b"\r\n") {
+            Some(LineEnding::CrLf)
+        } else if line.ends_with(b"\n") {
+            Some(LineEnding::Lf)
+        } else {
+            None
+        }
+    }
+}
+
+/// The side of a pull request diff that a suggestion comment is on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    /// The original version of the file.
+    Left,
+
+    /// The changed version of the file.
+    Right,
+}
+
+/// The author of a suggestion comment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+}
+
+/// A suggested change to a range of lines in a file, independent of the forge
+/// it came from.
+///
+/// Forge adapters like `github::ReviewComment` convert review comments to
+/// suggestions.
+#[derive(Clone, Debug)]
 pub struct Suggestion {
-    #[serde(rename = "diff_hunk")]
-    diff: String,
+    /// The ID of the comment on its forge.
+    pub id: String,
+
+    /// The ID of the review that the comment was submitted in, if any.
+    pub review_id: Option<String>,
+
+    /// The file the suggestion applies to.
+    pub path: String,
+
+    /// The commit whose version of the file the line numbers refer to. This
+    /// can also be a tree.
+    pub commit: String,
+
+    /// The first line replaced by the suggestion, starting at 1.
+    pub start_line: usize,
+
+    /// The last line replaced by the suggestion.
+    pub end_line: usize,
+
+    pub side: Side,
+
+    /// The comment text that precedes the suggestion block.
+    pub message: String,
+
+    /// The text that replaces the lines, with LF line endings.
+    pub replacement: String,
+
+    pub author: Author,
+
+    /// The time the comment was created, as an ISO 8601 timestamp.
+    pub created_at: String,
+
+    /// The URL of the comment.
+    pub url: Option<String>,
 
-    #[serde(rename = "body")]
-    comment: String,
+    /// Whether the diff lines that the comment was on have since changed in
+    /// the pull request.
+    pub outdated: bool,
 
-    #[serde(rename = "original_commit_id")]
-    commit: String,
+    /// Whether the comment's thread was resolved, if known.
+    pub resolved: Option<bool>,
+}
 
-    path: String,
+/// Where a suggestion's lines are in a version of its file.
+#[derive(Debug, PartialEq)]
+pub enum Location {
+    /// The original lines start at the given line, so the suggestion can be
+    /// applied.
+    Original(usize),
 
-    original_start_line: Option<usize>,
+    /// The replacement lines start at the given line, so the suggestion was
+    /// already applied.
+    Applied(usize),
 
-    #[serde(rename = "original_line")]
-    original_end_line: usize,
+    /// Neither the original nor the replacement lines were found.
+    Missing,
 }
 
+/// Number of lines around a suggestion used to find it in another version
+/// of its file, as in a diff.
+const CONTEXT_LINES: usize = 3;
+
 impl Suggestion {
-    /// Get the suggestion's commit SHA.
-    pub fn commit(&self) -> &str {
-        &self.commit
+    /// Deserialize a suggestion from a review comment JSON object in the
+    /// format returned by the GitHub API.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        github::from_json(json)
     }
 
-    /// Get the suggestion's file path.
-    pub fn path(&self) -> &str {
-        &self.path
+    /// Deserialize suggestions from `json`, which can be a single review
+    /// comment object, an array of comments, or newline-delimited comment
+    /// objects, in the format returned by the GitHub API.
+    pub fn list_from_json(json: &str) -> Result<Vec<Self>, Error> {
+        github::list_from_json(json)
+    }
+
+    /// Get the email address of the suggestion's author, or if they have
+    /// none, a no-reply address for their login on the comment's forge.
+    ///
+    /// Comments without a URL are taken to be from GitHub.
+    pub fn author_email(&self) -> String {
+        if !self.author.email.is_empty() {
+            return self.author.email.clone();
+        }
+
+        let host = self.url.as_deref()
+            .and_then(|url| Url::parse(url).ok())
+            .and_then(|url| url.host_str().map(str::to_owned));
+
+        match host.as_deref() {
+            None | Some("github.com") =>
+                format!("{}@users.noreply.github.com", self.author.name),
+            Some(host) => format!("{}@noreply.{}", self.author.name, host),
+        }
     }
 
-    /// Get the suggestion diff for the current repository.
-    pub fn diff(&self) -> Result<String, Error> {
-        let repo = Repository::open(".")?;
+    /// Get the suggestion diff for the repository in the current
+    /// environment.
+    ///
+    /// The repository is discovered from `GIT_DIR` and the current directory
+    /// the same way Git does it.
+    pub fn diff(&self) -> Result<Vec<u8>, Error> {
+        let repo = Repository::open_from_env()?;
 
         self.diff_with_repo(&repo)
     }
 
     /// Get the suggestion diff for `repo`.
-    fn diff_with_repo(&self, repo: &Repository) -> Result<String, Error> {
-        let commit = repo.find_commit(self.commit.parse()?)?;
-
+    ///
+    /// The diff is in the encoding of the file in the repository, which
+    /// need not be UTF-8.
+    pub fn diff_with_repo(&self, repo: &Repository) -> Result<Vec<u8>, Error> {
         let path = Path::new(&self.path);
 
-        let object = commit
-            .tree()?
-            .get_path(path)?
-            .to_object(&repo)?;
+        let blob = self.original_blob_with_repo(repo)?;
+        let new_buffer = self.apply_to_blob(repo, &blob)?;
+
+        let mut diff = Patch::from_blob_and_buffer(
+            &blob,
+            Some(path),
+            &new_buffer,
+            Some(path),
+            None,
+        )?;
+
+        Ok(diff.to_buf()?.to_vec())
+    }
 
-        let blob = object.as_blob()
-            .ok_or_else(|| Error::GitObjectNotBlob(object.id()))?;
+    /// Get the diff that undoes the suggestion in `repo`, the reverse of
+    /// `Suggestion::diff_with_repo()`.
+    pub fn reverse_diff_with_repo(
+        &self,
+        repo: &Repository,
+    ) -> Result<Vec<u8>, Error> {
+        let path = Path::new(&self.path);
 
-        let blob_reader = BufReader::new(blob.content());
-        let mut new = BufWriter::new(Vec::new());
-        self.apply_to(blob_reader, &mut new)?;
-        let new_buffer = new.into_inner()
-            .map_err(|e| Error::BufWriter {
-                source: e,
-                message: "unable to read right side of patch".to_owned(),
-            })?;
+        let blob = self.original_blob_with_repo(repo)?;
+        let new_buffer = self.apply_to_blob(repo, &blob)?;
 
-        let mut diff = Patch::from_blob_and_buffer(
-            blob,
-            Some(&path),
+        let mut diff = Patch::from_buffers(
             &new_buffer,
-            Some(&path),
+            Some(path),
+            blob.content(),
+            Some(path),
             None,
         )?;
 
-        Ok(
-            diff.to_buf()?
-                .as_str()
-                .ok_or_else(|| Error::InvalidUtf8("diff".to_owned()))?
-                .to_owned()
-        )
+        Ok(diff.to_buf()?.to_vec())
     }
 
     /// Create a Git blob with the contents of the file after applying the
-    /// suggestion.
+    /// suggestion in the repository in the current environment.
     pub fn blob(&self) -> Result<git2::Oid, Error> {
-        let repo = Repository::open(".")?;
-        let commit = repo.find_commit(self.commit.parse()?)?;
+        let repo = Repository::open_from_env()?;
 
-        let path = Path::new(&self.path);
+        self.blob_with_repo(&repo)
+    }
 
-        let object = commit
-            .tree()?
-            .get_path(path)?
-            .to_object(&repo)?;
+    /// Create a Git blob in `repo` with the contents of the file after
+    /// applying the suggestion.
+    pub fn blob_with_repo(&self, repo: &Repository) -> Result<git2::Oid, Error> {
+        let blob = self.original_blob_with_repo(repo)?;
+        let new_buffer = self.apply_to_blob(repo, &blob)?;
 
-        let blob = object.as_blob()
-            .ok_or_else(|| Error::GitObjectNotBlob(object.id()))?;
+        Ok(repo.blob(&new_buffer)?)
+    }
+
+    /// Get the blob for the suggestion's file at the suggestion's commit.
+    pub fn original_blob_with_repo<'r>(
+        &self,
+        repo: &'r Repository,
+    ) -> Result<git2::Blob<'r>, Error> {
+        let tree = repo.find_object(self.commit.parse()?, None)?
+            .peel_to_tree()?;
+
+        let object = tree
+            .get_path(Path::new(&self.path))?
+            .to_object(repo)?;
+
+        object.into_blob()
+            .map_err(|o| Error::GitObjectNotBlob(o.id()))
+    }
+
+    /// Get the contents of `blob` after applying the suggestion.
+    ///
+    /// Blobs are normally stored as UTF-8. A blob that isn't valid UTF-8 but
+    /// has a `working-tree-encoding` attribute was committed in that
+    /// encoding, so the suggestion is converted to it.
+    fn apply_to_blob(
+        &self,
+        repo: &Repository,
+        blob: &git2::Blob<'_>,
+    ) -> Result<Vec<u8>, Error> {
+        if blob.is_binary() {
+            return Err(Error::BinaryFile(self.path.clone()));
+        }
+
+        let encoding = self.blob_encoding(repo, blob)?;
+
+        let line_ending = if is_normalized(repo, &self.path, blob.content())? {
+            Some(LineEnding::Lf)
+        } else {
+            None
+        };
 
         let blob_reader = BufReader::new(blob.content());
         let mut new = BufWriter::new(Vec::new());
-        self.apply_to(blob_reader, &mut new)?;
-        let new_buffer = new.into_inner()
+        self.apply_to(blob_reader, &mut new, line_ending, encoding)?;
+
+        new.into_inner()
             .map_err(|e| Error::BufWriter {
                 source: e,
                 message: "unable to read right side of patch".to_owned(),
-            })?;
+            })
+    }
 
-        Ok(repo.blob(&new_buffer)?)
+    /// Get the encoding that `blob` is stored in.
+    ///
+    /// This is UTF-8 unless the blob isn't valid UTF-8 and the file has a
+    /// `working-tree-encoding` attribute.
+    fn blob_encoding(
+        &self,
+        repo: &Repository,
+        blob: &git2::Blob<'_>,
+    ) -> Result<&'static Encoding, Error> {
+        match working_tree_encoding(repo, &self.path)? {
+            Some(e) if std::str::from_utf8(blob.content()).is_err() => Ok(e),
+            _ => Ok(UTF_8),
+        }
     }
 
-    /// Extract suggestion code from a comment body.
-    fn suggestion_with_line_ending(
+    /// Find the suggestion's lines in `content`, another version of its file
+    /// in `repo`.
+    ///
+    /// The lines are searched for together with the lines around them in the
+    /// original file. If they're found in several places, the one closest to
+    /// the original position is used. Line endings are ignored.
+    pub fn locate(
         &self,
-        line_ending: &LineEnding,
-    ) -> Result<String, Error> {
-        let re = Regex::new(r"(?s).*(?-s)```\s*suggestion.*\n")?;
-        let s = re.replace(&self.comment, "");
-        let s = s.replace("```", "");
+        repo: &Repository,
+        content: &[u8],
+    ) -> Result<Location, Error> {
+        self.locate_with_context(repo, content, CONTEXT_LINES)
+    }
+
+    /// Find the suggestion's lines in `content` as with
+    /// `Suggestion::locate()`, together with `context` lines around them.
+    fn locate_with_context(
+        &self,
+        repo: &Repository,
+        content: &[u8],
+        context: usize,
+    ) -> Result<Location, Error> {
+        let blob = self.original_blob_with_repo(repo)?;
+        let encoding = self.blob_encoding(repo, &blob)?;
+
+        let original = lines(blob.content());
+        let start = match self.start_line.checked_sub(1) {
+            Some(start) => start.min(original.len()),
+            None => return Ok(Location::Missing),
+        };
+        let end = self.end_line.clamp(start, original.len());
+
+        let before = &original[start.saturating_sub(context)..start];
+        let after = &original[end..(end + context).min(original.len())];
+
+        let replacement = self.encode(&self.replacement, encoding)?;
+        let replacement = lines(&replacement);
+
+        let current = lines(content);
+
+        let find = |region: &[&[u8]]| {
+            let pattern: Vec<&[u8]> = before.iter()
+                .chain(region)
+                .chain(after)
+                .copied()
+                .collect();
 
-        // Suggestion blocks use CRLF by default.
-        if *line_ending == LineEnding::Lf {
-            return Ok(s.replace('\r', ""));
+            find_nearest(&current, &pattern, start - before.len())
+                .map(|i| i + before.len() + 1)
+        };
+
+        if let Some(line) = find(&replacement) {
+            return Ok(Location::Applied(line));
+        }
+
+        if let Some(line) = find(&original[start..end]) {
+            return Ok(Location::Original(line));
         }
 
-        Ok(s)
+        Ok(Location::Missing)
+    }
+
+    /// Apply the suggestion to `content`, a UTF-8 version of its file where
+    /// the suggestion's lines start at `start_line`, for example as found by
+    /// `Suggestion::locate()`.
+    pub fn apply_to_text(
+        &self,
+        content: &str,
+        start_line: usize,
+    ) -> Result<String, Error> {
+        let relocated = Suggestion {
+            start_line,
+            end_line: start_line
+                + self.end_line.saturating_sub(self.start_line),
+            ..self.clone()
+        };
+
+        let mut new_content = Vec::new();
+        relocated.apply_to(content.as_bytes(), &mut new_content, None, UTF_8)?;
+
+        Ok(std::str::from_utf8(&new_content)?.to_owned())
+    }
+
+    /// Get the replacement text with `line_ending` line endings.
+    fn replacement_with_line_ending(&self, line_ending: LineEnding) -> String {
+        match line_ending {
+            LineEnding::Lf => self.replacement.clone(),
+            LineEnding::CrLf => self.replacement.replace('\n', "\r\n"),
+        }
     }
 
-    /// Apply the suggestion to the current repository.
+    /// Apply the suggestion to the working tree of the repository in the
+    /// current environment.
     pub fn apply(&self) -> Result<(), Error> {
-        let repo = Repository::open(".")?;
+        let repo = Repository::open_from_env()?;
 
-        let diff_text = self.diff_with_repo(&repo)?;
-        let diff = git2::Diff::from_buffer(diff_text.as_bytes())?;
+        self.apply_with_repo(&repo)
+    }
+
+    /// Apply the suggestion to the working tree of `repo`.
+    pub fn apply_with_repo(&self, repo: &Repository) -> Result<(), Error> {
+        self.apply_at(repo, git2::ApplyLocation::WorkDir)
+    }
+
+    /// Apply the suggestion to `location` in `repo`, which can be the working
+    /// tree, the index, or both.
+    ///
+    /// If the file there has changed since the suggestion's commit, for
+    /// example because other suggestions on it were applied, the suggestion
+    /// is moved onto the lines found by `Suggestion::locate()`.
+    ///
+    /// When applying to the working tree only, if the file has a
+    /// `working-tree-encoding` attribute and is stored as UTF-8, the patch is
+    /// converted to the working tree encoding before it's applied.
+    pub fn apply_at(
+        &self,
+        repo: &Repository,
+        location: git2::ApplyLocation,
+    ) -> Result<(), Error> {
+        let original = self.original_blob_with_repo(repo)?;
+
+        let diff_text = match self.current_blob_at(repo, location)? {
+            Some(current) if current.id() != original.id() =>
+                self.relocated_diff_with_repo(repo, &current)?,
+            _ => self.diff_with_repo(repo)?,
+        };
+
+        self.apply_diff_at(repo, diff_text, location)
+    }
+
+    /// Get the blob of the suggestion's file as it is in `location` in
+    /// `repo`, if it's there.
+    ///
+    /// The working tree file is run through the same filters as when it's
+    /// added to the index.
+    fn current_blob_at<'r>(
+        &self,
+        repo: &'r Repository,
+        location: git2::ApplyLocation,
+    ) -> Result<Option<git2::Blob<'r>>, Error> {
+        let path = Path::new(&self.path);
 
-        repo.apply(
-            &diff,
-            git2::ApplyLocation::WorkDir,
+        let id = match location {
+            git2::ApplyLocation::WorkDir => {
+                let workdir = match repo.workdir() {
+                    Some(workdir) => workdir,
+                    None => return Ok(None),
+                };
+
+                let content = match fs::read(workdir.join(path)) {
+                    Ok(content) => content,
+                    Err(_) => return Ok(None),
+                };
+
+                let mut writer = repo.blob_writer(Some(path))?;
+                writer.write_all(&content)
+                    .map_err(|e| Error::Io {
+                        source: e,
+                        message: "Unable to write blob".to_owned(),
+                    })?;
+
+                writer.commit()?
+            },
+            _ => match repo.index()?.get_path(path, 0) {
+                Some(entry) => entry.id,
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(repo.find_blob(id)?))
+    }
+
+    /// Get the suggestion diff against `current`, a changed version of its
+    /// file in `repo`.
+    ///
+    /// The lines around the suggestion may have been changed too, so fewer
+    /// of them are used until the suggestion's lines are found. If they
+    /// can't be found, the diff against the original file is used, so that
+    /// applying it fails as usual.
+    fn relocated_diff_with_repo(
+        &self,
+        repo: &Repository,
+        current: &git2::Blob<'_>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut location = Location::Missing;
+
+        for context in (0..=CONTEXT_LINES).rev() {
+            location =
+                self.locate_with_context(repo, current.content(), context)?;
+
+            if location != Location::Missing {
+                break;
+            }
+        }
+
+        let line = match location {
+            Location::Original(line) => line,
+            Location::Applied(_) | Location::Missing =>
+                return self.diff_with_repo(repo),
+        };
+
+        let relocated = Suggestion {
+            start_line: line,
+            end_line: line + self.end_line.saturating_sub(self.start_line),
+            ..self.clone()
+        };
+
+        let path = Path::new(&self.path);
+        let new_buffer = relocated.apply_to_blob(repo, current)?;
+
+        let mut diff = Patch::from_blob_and_buffer(
+            current,
+            Some(path),
+            &new_buffer,
+            Some(path),
             None,
         )?;
 
+        Ok(diff.to_buf()?.to_vec())
+    }
+
+    /// Undo the suggestion in `location` in `repo` by applying the reverse
+    /// diff.
+    ///
+    /// Fails with a `git2::ErrorCode::ApplyFail` error if the lines around
+    /// the suggestion have changed since it was applied.
+    pub fn unapply_at(
+        &self,
+        repo: &Repository,
+        location: git2::ApplyLocation,
+    ) -> Result<(), Error> {
+        let diff_text = self.reverse_diff_with_repo(repo)?;
+
+        self.apply_diff_at(repo, diff_text, location)
+    }
+
+    /// Apply `diff_text`, a diff of the suggestion's file, to `location` in
+    /// `repo`.
+    fn apply_diff_at(
+        &self,
+        repo: &Repository,
+        mut diff_text: Vec<u8>,
+        location: git2::ApplyLocation,
+    ) -> Result<(), Error> {
+        if let git2::ApplyLocation::WorkDir = location {
+            if let Some(encoding) = working_tree_encoding(repo, &self.path)? {
+                if let Ok(text) = std::str::from_utf8(&diff_text) {
+                    diff_text = self.encode(text, encoding)?.into_owned();
+                }
+            }
+        }
+
+        let diff = git2::Diff::from_buffer(&diff_text)?;
+
+        repo.apply(&diff, location, None)?;
+
         Ok(())
     }
 
     /// Apply the patch in `reader` to `writer`.
+    ///
+    /// Lines outside the suggestion keep their original line endings. The
+    /// suggestion's lines use `line_ending` if given, otherwise the line
+    /// ending of the lines they replace. The suggestion is written in
+    /// `encoding`. If the original doesn't end with a newline, neither does
+    /// the result.
     fn apply_to<R: BufRead, W: Write>(
         &self,
-        reader: R,
+        mut reader: R,
         writer: &mut W,
+        line_ending: Option<LineEnding>,
+        encoding: &'static Encoding,
     ) -> Result<(), Error> {
-        let mut line_ending = LineEnding::Lf;
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)
+            .map_err(|e| Error::Io {
+                source: e,
+                message: "Unable to read file".to_owned(),
+            })?;
 
-        for (i, line) in reader.lines().enumerate() {
+        let lines: Vec<_> = content.split_inclusive(|b| *b == b'\n').collect();
+
+        let ends_with_newline = lines.last()
+            .map_or(true, |line| LineEnding::of(line).is_some());
+
+        let mut new_content = Vec::with_capacity(content.len());
+
+        for (i, line) in lines.iter().enumerate() {
             let line_number = i + 1;
 
-            let line = line.map_err(|e| Error::Io {
-                source: e,
-                message: "Unable to read line".to_owned(),
-            })?;
+            if line_number == self.end_line {
+                let line_ending = line_ending
+                    .unwrap_or_else(|| region_line_ending(&lines[..=i]));
+
+                let suggestion = self.replacement_with_line_ending(line_ending);
 
-            // Determine which line endings the file uses by looking at the
-            // first line.
-            if line_number == 1 && is_line_crlf(&line) {
-                line_ending = LineEnding::CrLf;
+                new_content.extend_from_slice(
+                    &self.encode(&suggestion, encoding)?,
+                );
+            } else if line_number < self.start_line
+                    || line_number > self.end_line {
+                new_content.extend_from_slice(line);
             }
+        }
 
-            if line_number == self.original_end_line {
-                write!(
-                    writer,
-                    "{}",
-                    self.suggestion_with_line_ending(&line_ending)?,
-                ).map_err(|e| Error::Io {
-                    source: e,
-                    message: "Write error".to_owned(),
-                })?;
-            } else if self.original_start_line.is_none()
-                    || line_number < self.original_start_line.unwrap()
-                    || line_number > self.original_end_line {
-                writeln!(writer, "{}", line)
-                    .map_err(|e| Error::Io {
-                        source: e,
-                        message: "Write error".to_owned(),
-                    })?;
+        // When the suggestion replaces or removes the last line, the line
+        // that now ends the file must not end with a newline either.
+        if !ends_with_newline && self.end_line >= lines.len() {
+            if new_content.ends_with(b"\r\n") {
+                new_content.truncate(new_content.len() - 2);
+            } else if new_content.ends_with(b"\n") {
+                new_content.truncate(new_content.len() - 1);
             }
         }
 
-        Ok(())
+        writer.write_all(&new_content)
+            .map_err(|e| Error::Io {
+                source: e,
+                message: "Write error".to_owned(),
+            })
+    }
+
+    /// Convert `text` to `encoding`, failing if it has characters that can't
+    /// be represented in the encoding.
+    fn encode<'a>(
+        &self,
+        text: &'a str,
+        encoding: &'static Encoding,
+    ) -> Result<Cow<'a, [u8]>, Error> {
+        let (bytes, _, had_errors) = encoding.encode(text);
+
+        if had_errors {
+            return Err(Error::Encode {
+                path: self.path.clone(),
+                encoding: encoding.name(),
+            });
+        }
+
+        Ok(bytes)
     }
 }
 
-/// Determine the line ending for `line`.
+/// Split a review comment body into the message that precedes the suggestion
+/// block, and the suggested replacement text with LF line endings.
 ///
-/// If the second-to-last character on the first line is "\r", assume CRLF.
-/// Otherwise, default to LF.
-fn is_line_crlf(line: &str) -> bool {
-    if let Some(c) = line.chars().rev().nth(2) {
-        if c == '\r' {
-            return true;
-        }
+/// The replacement is the text after the line that opens the last
+/// ```` ```suggestion ```` block, without fences.
+pub fn parse_comment(body: &str) -> (String, String) {
+    let message = match body.find("```") {
+        Some(i) => &body[..i],
+        None => body,
+    };
+
+    let start = suggestion_start(body).unwrap_or(0);
+
+    // Suggestion blocks use CRLF by default.
+    let replacement = body[start..]
+        .replace("```", "")
+        .replace("\r\n", "\n");
+
+    (message.trim().to_owned(), replacement)
+}
+
+/// Determine whether a review comment body contains a suggestion block.
+pub fn has_suggestion(body: &str) -> bool {
+    suggestion_start(body).is_some()
+}
+
+/// Get the position after the line that opens the last suggestion block in
+/// `body`.
+fn suggestion_start(body: &str) -> Option<usize> {
+    body.match_indices("```")
+        .filter_map(|(i, fence)| {
+            let after_fence = &body[i + fence.len()..];
+            let info = after_fence.trim_start();
+
+            if !info.starts_with("suggestion") {
+                return None;
+            }
+
+            let info_start = body.len() - info.len();
+
+            Some(
+                body[info_start..].find('\n')
+                    .map(|n| info_start + n + 1)
+                    .unwrap_or(body.len())
+            )
+        })
+        .last()
+}
+
+/// Split `content` into lines without their line endings.
+fn lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|b| *b == b'\n')
+        .map(|line| {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+
+            line.strip_suffix(b"\r").unwrap_or(line)
+        })
+        .collect()
+}
+
+/// Find the index in `lines` where `pattern` occurs, choosing the occurrence
+/// closest to `near` if there are several.
+fn find_nearest(lines: &[&[u8]], pattern: &[&[u8]], near: usize) -> Option<usize> {
+    if pattern.len() > lines.len() {
+        return None;
     }
 
-    false
+    (0..=lines.len() - pattern.len())
+        .filter(|&i| lines[i..i + pattern.len()] == *pattern)
+        .min_by_key(|&i| (i as isize - near as isize).abs())
+}
+
+/// Get the line ending of the last terminated line in `lines`, which end
+/// with the last line of the suggestion's region. Defaults to LF.
+fn region_line_ending(lines: &[&[u8]]) -> LineEnding {
+    lines.iter()
+        .rev()
+        .find_map(|line| LineEnding::of(line))
+        .unwrap_or(LineEnding::Lf)
+}
+
+/// Determine whether Git stores `path` with normalised LF line endings in
+/// `repo`, based on the `text` and `eol` attributes and `core.autocrlf`.
+///
+/// With `text=auto` or `core.autocrlf`, files that already contain CRLF
+/// line endings in `content` are not normalised, as in Git.
+fn is_normalized(
+    repo: &Repository,
+    path: &str,
+    content: &[u8],
+) -> Result<bool, Error> {
+    let path = Path::new(path);
+    let flags = git2::AttrCheckFlags::FILE_THEN_INDEX;
+
+    let has_crlf = content.windows(2).any(|w| w == b"\r\n");
+
+    match attr_value(repo.get_attr(path, "text", flags)?) {
+        AttrValue::True => return Ok(true),
+        AttrValue::False => return Ok(false),
+        AttrValue::String("auto") => return Ok(!has_crlf),
+        _ => (),
+    }
+
+    if let AttrValue::String(_) = attr_value(repo.get_attr(path, "eol", flags)?) {
+        return Ok(true);
+    }
+
+    let autocrlf = match repo.config()?.get_string("core.autocrlf") {
+        Ok(v) => v,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    match autocrlf.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" | "input" => Ok(!has_crlf),
+        _ => Ok(false),
+    }
+}
+
+/// Get the encoding named in the `working-tree-encoding` attribute of `path`
+/// in `repo`.
+///
+/// Returns `Ok(None)` if the attribute isn't set or names UTF-8.
+fn working_tree_encoding(
+    repo: &Repository,
+    path: &str,
+) -> Result<Option<&'static Encoding>, Error> {
+    let value = repo.get_attr(
+        Path::new(path),
+        "working-tree-encoding",
+        git2::AttrCheckFlags::FILE_THEN_INDEX,
+    )?;
+
+    match attr_value(value) {
+        AttrValue::String(label) => {
+            let encoding = Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| Error::UnknownEncoding(label.to_owned()))?;
+
+            if encoding == UTF_8 {
+                Ok(None)
+            } else {
+                Ok(Some(encoding))
+            }
+        },
+        _ => Ok(None),
+    }
+}
+
+/// The value of a Git attribute.
+#[derive(Debug, PartialEq)]
+enum AttrValue<'a> {
+    True,
+    False,
+    Unspecified,
+    String(&'a str),
+}
+
+/// Interpret an attribute value returned by `Repository::get_attr()`.
+fn attr_value(value: Option<&str>) -> AttrValue<'_> {
+    match value {
+        None => AttrValue::Unspecified,
+        Some("[internal]__TRUE__") => AttrValue::True,
+        Some("[internal]__FALSE__") => AttrValue::False,
+        Some("[internal]__UNSET__") => AttrValue::Unspecified,
+        Some(s) => AttrValue::String(s),
+    }
 }
 
 
@@ -262,6 +861,89 @@ fn is_line_crlf(line: &str) -> bool {
 mod tests {
     use super::*;
 
+    use std::io::Cursor;
+
+    /// Commit a file at `path` with `content` in `repo`.
+    fn commit_file(repo: &Repository, path: &str, content: &[u8]) -> git2::Oid {
+        let mut index = repo.index().unwrap();
+        index.add_frombuffer(
+            &git2::IndexEntry {
+                ctime: git2::IndexTime::new(0, 0),
+                mtime: git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: 0o100644,
+                uid: 0,
+                gid: 0,
+                file_size: content.len() as u32,
+                id: git2::Oid::zero(),
+                flags: 0,
+                flags_extended: 0,
+                path: path.as_bytes().to_vec(),
+            },
+            content,
+        ).unwrap();
+        let tree_oid = index.write_tree().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+
+        let author = git2::Signature::now(
+            "Oshino Shinobu",
+            "oshino.shinobu@example.com",
+        ).unwrap();
+
+        repo.commit(
+            Some("HEAD"),
+            &author,
+            &author,
+            "Sample commit",
+            &tree,
+            &[],
+        ).unwrap()
+    }
+
+    /// Create a suggestion that replaces `start_line` to `end_line` with
+    /// `suggestion`.
+    fn suggestion(
+        commit: &str,
+        path: &str,
+        start_line: Option<usize>,
+        end_line: usize,
+        suggestion: &str,
+    ) -> Suggestion {
+        Suggestion {
+            id: "1".to_owned(),
+            review_id: None,
+            path: path.to_owned(),
+            commit: commit.to_owned(),
+            start_line: start_line.unwrap_or(end_line),
+            end_line,
+            side: Side::Right,
+            message: String::new(),
+            replacement: suggestion.replace("\r\n", "\n"),
+            author: reviewer(),
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        }
+    }
+
+    fn reviewer() -> Author {
+        Author {
+            name: "oshino-shinobu".to_owned(),
+            email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+        }
+    }
+
+    /// Apply `suggestion` to `original` and return the result.
+    fn apply(suggestion: &Suggestion, original: &[u8]) -> Vec<u8> {
+        let mut actual = Cursor::new(Vec::new());
+        suggestion.apply_to(Cursor::new(original), &mut actual, None, UTF_8)
+            .unwrap();
+
+        actual.into_inner()
+    }
+
     #[test]
     fn suggestion_diff_with_repo_generates_diff() {
         use tempfile::tempdir;
@@ -320,15 +1002,22 @@ mod tests {
         ).unwrap();
 
         let suggestion = Suggestion {
-            diff: "".to_owned(),
-            comment: r#"``` suggestion
-     He took his vorpal sword in hand:
-      Long time the manxome foe he sought—
-```"#.to_owned(),
-            commit: commit.to_string(),
+            id: "1".to_owned(),
+            review_id: None,
             path: path.to_owned(),
-            original_start_line: Some(7),
-            original_end_line: 8,
+            commit: commit.to_string(),
+            start_line: 7,
+            end_line: 8,
+            side: Side::Right,
+            message: String::new(),
+            replacement: r#"     He took his vorpal sword in hand:
+      Long time the manxome foe he sought—
+"#.to_owned(),
+            author: reviewer(),
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
         };
 
         let expected = r#"diff --git a/poems/Jabberwocky.txt b/poems/Jabberwocky.txt
@@ -348,7 +1037,8 @@ index 89840a2..06acdfc 100644
 "#;
 
         assert_eq!(
-            suggestion.diff_with_repo(&repo).unwrap(),
+            std::str::from_utf8(&suggestion.diff_with_repo(&repo).unwrap())
+                .unwrap(),
             expected,
         );
     }
@@ -374,15 +1064,22 @@ index 89840a2..06acdfc 100644
         write!(original_buffer, "{}", original).unwrap();
 
         let suggestion = Suggestion {
-            diff: "".to_owned(),
-            comment: r#"``` suggestion
-     He took his vorpal sword in hand:
-      Long time the manxome foe he sought—
-```"#.to_owned(),
-            commit: "".to_owned(),
+            id: "1".to_owned(),
+            review_id: None,
             path: "".to_owned(),
-            original_start_line: Some(7),
-            original_end_line: 8,
+            commit: "".to_owned(),
+            start_line: 7,
+            end_line: 8,
+            side: Side::Right,
+            message: String::new(),
+            replacement: r#"     He took his vorpal sword in hand:
+      Long time the manxome foe he sought—
+"#.to_owned(),
+            author: reviewer(),
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
         };
 
         let expected = r#"
@@ -399,11 +1096,438 @@ index 89840a2..06acdfc 100644
 
         let original_reader = Cursor::new(original_buffer);
         let mut actual = Cursor::new(Vec::new());
-        suggestion.apply_to(original_reader, &mut actual).unwrap();
+        suggestion.apply_to(original_reader, &mut actual, None, UTF_8)
+            .unwrap();
 
         assert_eq!(
             std::str::from_utf8(&actual.into_inner()).unwrap(),
             expected,
         );
     }
+
+    #[test]
+    fn suggestion_apply_to_uses_crlf_of_replaced_lines() {
+        let suggestion = suggestion(
+            "",
+            "",
+            Some(2),
+            3,
+            "He took his vorpal sword in hand:\r\n\
+                Long time the manxome foe he sought—\r\n",
+        );
+
+        let original = b"\r\n\
+            He took his vorpal blade in hand:\r\n\
+            Long time the manxome foe he sought--\r\n\
+            So rested he by the Tumtum tree,\r\n";
+
+        assert_eq!(
+            std::str::from_utf8(&apply(&suggestion, original)).unwrap(),
+            "\r\n\
+            He took his vorpal sword in hand:\r\n\
+            Long time the manxome foe he sought—\r\n\
+            So rested he by the Tumtum tree,\r\n",
+        );
+    }
+
+    #[test]
+    fn suggestion_apply_to_preserves_mixed_line_endings() {
+        let suggestion = suggestion(
+            "",
+            "",
+            None,
+            2,
+            "He took his vorpal sword in hand:\r\n",
+        );
+
+        let original = b"\n\
+            He took his vorpal blade in hand:\r\n\
+            Long time the manxome foe he sought--\n\
+            So rested he by the Tumtum tree,\r\n";
+
+        assert_eq!(
+            std::str::from_utf8(&apply(&suggestion, original)).unwrap(),
+            "\n\
+            He took his vorpal sword in hand:\r\n\
+            Long time the manxome foe he sought--\n\
+            So rested he by the Tumtum tree,\r\n",
+        );
+
+        let original = b"\r\n\
+            He took his vorpal blade in hand:\n\
+            Long time the manxome foe he sought--\r\n";
+
+        assert_eq!(
+            std::str::from_utf8(&apply(&suggestion, original)).unwrap(),
+            "\r\n\
+            He took his vorpal sword in hand:\n\
+            Long time the manxome foe he sought--\r\n",
+        );
+    }
+
+    #[test]
+    fn suggestion_blob_with_repo_normalizes_text_files() {
+        use tempfile::tempdir;
+
+
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        std::fs::write(
+            git_root.path().join(".gitattributes"),
+            "*.txt text\n",
+        ).unwrap();
+
+        let path = "poems/Jabberwocky.txt";
+        let commit = commit_file(
+            &repo,
+            path,
+            b"He took his vorpal blade in hand:\r\n\
+                Long time the manxome foe he sought--\r\n",
+        );
+
+        let suggestion = suggestion(
+            &commit.to_string(),
+            path,
+            None,
+            1,
+            "He took his vorpal sword in hand:\r\n",
+        );
+
+        let blob = suggestion.blob_with_repo(&repo).unwrap();
+
+        assert_eq!(
+            repo.find_blob(blob).unwrap().content(),
+            &b"He took his vorpal sword in hand:\n\
+                Long time the manxome foe he sought--\r\n"[..],
+        );
+    }
+
+    #[test]
+    fn suggestion_apply_to_keeps_missing_newline_at_end_of_file() {
+        let original = b"He took his vorpal blade in hand:\n\
+            Long time the manxome foe he sought--";
+
+        let suggestion_before_last_line = suggestion(
+            "",
+            "",
+            None,
+            1,
+            "He took his vorpal sword in hand:\r\n",
+        );
+
+        assert_eq!(
+            std::str::from_utf8(
+                &apply(&suggestion_before_last_line, original),
+            ).unwrap(),
+            "He took his vorpal sword in hand:\n\
+            Long time the manxome foe he sought--",
+        );
+
+        let suggestion_on_last_line = suggestion(
+            "",
+            "",
+            None,
+            2,
+            "Long time the manxome foe he sought—\r\n",
+        );
+
+        assert_eq!(
+            std::str::from_utf8(
+                &apply(&suggestion_on_last_line, original),
+            ).unwrap(),
+            "He took his vorpal blade in hand:\n\
+            Long time the manxome foe he sought—",
+        );
+    }
+
+    #[test]
+    fn suggestion_apply_to_deletes_last_line_without_newline() {
+        let original = b"He took his vorpal blade in hand:\n\
+            Long time the manxome foe he sought--";
+
+        let last_line = suggestion("", "", None, 2, "");
+
+        assert_eq!(
+            std::str::from_utf8(&apply(&last_line, original)).unwrap(),
+            "He took his vorpal blade in hand:",
+        );
+
+        let all_lines = suggestion("", "", Some(1), 2, "");
+
+        assert_eq!(
+            std::str::from_utf8(&apply(&all_lines, original)).unwrap(),
+            "",
+        );
+    }
+
+    #[test]
+    fn suggestion_diff_with_repo_refuses_binary_files() {
+        use tempfile::tempdir;
+
+
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let path = "jabberwock.png";
+        let commit = commit_file(&repo, path, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+
+        let suggestion = suggestion(
+            &commit.to_string(),
+            path,
+            None,
+            1,
+            "vorpal\r\n",
+        );
+
+        match suggestion.diff_with_repo(&repo) {
+            Err(Error::BinaryFile(p)) => assert_eq!(p, path),
+            r => panic!("expected binary file error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn suggestion_blob_with_repo_encodes_legacy_blobs() {
+        use tempfile::tempdir;
+
+
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        std::fs::write(
+            git_root.path().join(".gitattributes"),
+            "*.txt working-tree-encoding=ISO-8859-1\n",
+        ).unwrap();
+
+        let path = "poems/Jabberwocky.txt";
+        let commit = commit_file(
+            &repo,
+            path,
+            b"\xabBeware the Jabberwock, my son!\xbb\n\
+                He took his vorpal blade in hand:\n",
+        );
+
+        let suggestion = suggestion(
+            &commit.to_string(),
+            path,
+            None,
+            2,
+            "He took his vorpal sword in hand\u{a0}:\r\n",
+        );
+
+        let blob = suggestion.blob_with_repo(&repo).unwrap();
+
+        assert_eq!(
+            repo.find_blob(blob).unwrap().content(),
+            &b"\xabBeware the Jabberwock, my son!\xbb\n\
+                He took his vorpal sword in hand\xa0:\n"[..],
+        );
+    }
+
+    #[test]
+    fn suggestion_apply_with_repo_uses_working_tree_encoding() {
+        use tempfile::tempdir;
+
+
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        std::fs::write(
+            git_root.path().join(".gitattributes"),
+            "*.txt working-tree-encoding=Shift_JIS\n",
+        ).unwrap();
+
+        let path = "haiku.txt";
+        let original = "古池や\n蛙飛び込む\n水の音\n";
+        let commit = commit_file(&repo, path, original.as_bytes());
+
+        let (working_tree, _, _) = encoding_rs::SHIFT_JIS.encode(original);
+        std::fs::write(git_root.path().join(path), &working_tree).unwrap();
+
+        let suggestion = suggestion(
+            &commit.to_string(),
+            path,
+            None,
+            2,
+            "蛙飛びこむ\r\n",
+        );
+
+        suggestion.apply_with_repo(&repo).unwrap();
+
+        let (expected, _, _) = encoding_rs::SHIFT_JIS
+            .encode("古池や\n蛙飛びこむ\n水の音\n");
+
+        assert_eq!(
+            std::fs::read(git_root.path().join(path)).unwrap(),
+            expected.into_owned(),
+        );
+    }
+
+    #[test]
+    fn suggestion_apply_with_repo_moves_onto_earlier_suggestions() {
+        use tempfile::tempdir;
+
+
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let path = "Jabberwocky.txt";
+        let original: String = (1..=20)
+            .map(|i| format!("line {}\n", i))
+            .collect();
+        let commit = commit_file(&repo, path, original.as_bytes());
+        std::fs::write(git_root.path().join(path), &original).unwrap();
+
+        let commit = commit.to_string();
+        let suggestions = [
+            // Adds a line before the others.
+            suggestion(&commit, path, None, 2, "line 2\nline 2a\n"),
+            // Within the context lines of the first one.
+            suggestion(&commit, path, None, 4, "line four\n"),
+            suggestion(&commit, path, Some(15), 16, "line 15-16\n"),
+        ];
+
+        for suggestion in &suggestions {
+            suggestion.apply_with_repo(&repo).unwrap();
+        }
+
+        let expected: String = (1..=20)
+            .map(|i| match i {
+                2 => "line 2\nline 2a\n".to_owned(),
+                4 => "line four\n".to_owned(),
+                15 => "line 15-16\n".to_owned(),
+                16 => String::new(),
+                i => format!("line {}\n", i),
+            })
+            .collect();
+
+        assert_eq!(
+            std::fs::read_to_string(git_root.path().join(path)).unwrap(),
+            expected,
+        );
+    }
+
+    #[test]
+    fn suggestion_author_email_falls_back_to_no_reply_address() {
+        let mut suggestion = suggestion("89840a2", "poem.txt", None, 1, "");
+        assert_eq!(
+            suggestion.author_email(),
+            "1+oshino-shinobu@users.noreply.github.com",
+        );
+
+        suggestion.author.email = String::new();
+        assert_eq!(
+            suggestion.author_email(),
+            "oshino-shinobu@users.noreply.github.com",
+        );
+
+        suggestion.url = Some(
+            "https://codeberg.org/teddywing/git-suggestion/pulls/1#issuecomment-11"
+                .to_owned(),
+        );
+        assert_eq!(
+            suggestion.author_email(),
+            "oshino-shinobu@noreply.codeberg.org",
+        );
+    }
+
+    #[test]
+    fn suggestion_locate_finds_moved_lines() {
+        use tempfile::tempdir;
+
+
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let path = "poems/Jabberwocky.txt";
+        let original = "Beware the Jabberwock, my son!\n\
+            The jaws that bite, the claws that catch!\n\
+            Beware the Jubjub bird, and shun\n\
+            The frumious Bandersnatch!\n\
+            He took his vorpal blade in hand:\n\
+            Long time the manxome foe he sought--\n";
+        let commit = commit_file(&repo, path, original.as_bytes());
+
+        let suggestion = suggestion(
+            &commit.to_string(),
+            path,
+            None,
+            4,
+            "The frumious Bandersnatch!!\r\n",
+        );
+
+        let moved = format!("Jabberwocky\n\n{}", original);
+        assert_eq!(
+            suggestion.locate(&repo, moved.as_bytes()).unwrap(),
+            Location::Original(6),
+        );
+
+        let applied = original.replace("Bandersnatch!", "Bandersnatch!!");
+        assert_eq!(
+            suggestion.locate(&repo, applied.as_bytes()).unwrap(),
+            Location::Applied(4),
+        );
+
+        let changed = original.replace("Bandersnatch!", "Bandersnatch.");
+        assert_eq!(
+            suggestion.locate(&repo, changed.as_bytes()).unwrap(),
+            Location::Missing,
+        );
+
+        let unplaced = Suggestion {
+            start_line: 0,
+            end_line: 0,
+            ..suggestion
+        };
+        assert_eq!(
+            unplaced.locate(&repo, original.as_bytes()).unwrap(),
+            Location::Missing,
+        );
+    }
+
+    #[test]
+    fn parse_comment_uses_last_suggestion_block() {
+        let (message, replacement) = parse_comment(
+            "Use the sword.\r\n\
+                ```rust\r\n\
+                let blade = \"dull\";\r\n\
+                ```\r\n\
+                ```suggestion\r\n\
+                He took his vorpal sword in hand:\r\n\
+                ```",
+        );
+
+        assert_eq!(message, "Use the sword.");
+        assert_eq!(replacement, "He took his vorpal sword in hand:\n");
+    }
+
+    #[test]
+    fn suggestion_list_from_json_reads_arrays_and_ndjson() {
+        let comment = r#"{
+            "id": 459691747,
+            "body": "```suggestion\nsword\n```",
+            "original_commit_id": "89840a2",
+            "path": "poems/Jabberwocky.txt",
+            "original_start_line": null,
+            "original_line": 7,
+            "position": 7,
+            "user": { "login": "oshino-shinobu", "id": 1 },
+            "created_at": "2020-07-23T17:20:29Z"
+        }"#;
+
+        let single = Suggestion::list_from_json(comment).unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].path, "poems/Jabberwocky.txt");
+
+        let array = format!("[{}, {}]", comment, comment);
+        assert_eq!(Suggestion::list_from_json(&array).unwrap().len(), 2);
+
+        let ndjson = format!(
+            "{}\n{}\n{}\n",
+            comment.replace('\n', ""),
+            comment.replace('\n', ""),
+            comment.replace('\n', ""),
+        );
+        assert_eq!(Suggestion::list_from_json(&ndjson).unwrap().len(), 3);
+    }
 }
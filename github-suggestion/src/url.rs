@@ -18,7 +18,6 @@ use std::str::FromStr;
 
 use thiserror::Error;
 
-use url;
 use url::Url;
 
 
@@ -28,6 +27,9 @@ pub enum Error {
     #[error("Unable to parse URL")]
     Url(#[from] url::ParseError),
 
+    #[error("URL has no host")]
+    NoHost,
+
     #[error("URL has no path")]
     NoPath,
 
@@ -36,18 +38,54 @@ pub enum Error {
 
     #[error("Unable to parse owner or repo")]
     NoOwnerRepo,
+
+    #[error("URL has no pull request number")]
+    NoPull,
+}
+
+/// The kind of forge that a suggestion comment URL points at.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Forge {
+    /// GitHub, where comments can be fetched by ID alone.
+    Github,
+
+    /// Gitea or Forgejo at `base_url`, where comments are fetched through
+    /// their pull request.
+    Gitea {
+        base_url: String,
+        pull: String,
+    },
+}
+
+/// What a suggestion URL refers to.
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    /// A single review comment with the given ID.
+    Comment(String),
+
+    /// All the comments of review `id` on pull request `pull`.
+    Review {
+        pull: String,
+        id: String,
+    },
 }
 
-/// The important parts of a suggestion comment URL.
+/// The important parts of a suggestion comment or review URL.
 #[derive(Debug)]
 pub struct SuggestionUrl {
+    pub host: String,
     pub owner: String,
     pub repo: String,
-    pub comment_id: String,
+    pub target: Target,
+    pub forge: Forge,
 }
 
-/// Parses a URL with the format
-/// `https://github.com/teddywing/git-suggestion/pull/1#discussion_r459691747`.
+/// Parses a GitHub URL with the format
+/// `https://github.com/teddywing/git-suggestion/pull/1#discussion_r459691747`,
+/// a GitHub review URL with the format
+/// `https://github.com/teddywing/git-suggestion/pull/1#pullrequestreview-123`,
+/// or a Gitea or Forgejo URL with the format
+/// `https://codeberg.org/teddywing/git-suggestion/pulls/1#issuecomment-1234`.
 impl FromStr for SuggestionUrl {
     type Err = Error;
 
@@ -61,12 +99,158 @@ impl FromStr for SuggestionUrl {
             return Err(Error::NoOwnerRepo);
         }
 
+        let host = url.host_str()
+            .ok_or(Error::NoHost)?;
+        let fragment = url.fragment()
+            .ok_or(Error::NoFragment)?;
+
+        if let Some(id) = fragment.strip_prefix("pullrequestreview-") {
+            let pull = match path.get(2..4) {
+                Some(["pull", pull]) => pull,
+                _ => return Err(Error::NoPull),
+            };
+
+            return Ok(SuggestionUrl {
+                host: host.to_owned(),
+                owner: path[0].to_owned(),
+                repo: path[1].to_owned(),
+                target: Target::Review {
+                    pull: (*pull).to_owned(),
+                    id: id.to_owned(),
+                },
+                forge: Forge::Github,
+            });
+        }
+
+        let (comment_id, forge) = match fragment.strip_prefix("issuecomment-") {
+            Some(id) => {
+                let pull = match path.get(2..4) {
+                    Some(["pulls", pull]) => pull,
+                    _ => return Err(Error::NoPull),
+                };
+
+                let base_url = match url.port() {
+                    Some(port) =>
+                        format!("{}://{}:{}", url.scheme(), host, port),
+                    None => format!("{}://{}", url.scheme(), host),
+                };
+
+                (
+                    id.to_owned(),
+                    Forge::Gitea {
+                        base_url,
+                        pull: (*pull).to_owned(),
+                    },
+                )
+            },
+            None => (fragment.replacen("discussion_r", "", 1), Forge::Github),
+        };
+
         Ok(SuggestionUrl {
+            host: host.to_owned(),
             owner: path[0].to_owned(),
             repo: path[1].to_owned(),
-            comment_id: url.fragment()
-                .ok_or(Error::NoFragment)?
-                .replacen("discussion_r", "", 1),
+            target: Target::Comment(comment_id),
+            forge,
         })
     }
 }
+
+/// The important parts of a GitHub pull request URL.
+#[derive(Debug)]
+pub struct PullUrl {
+    pub host: String,
+    pub owner: String,
+    pub repo: String,
+    pub number: String,
+}
+
+/// Parses a GitHub pull request URL with the format
+/// `https://github.com/teddywing/git-suggestion/pull/1`. Any fragment or
+/// further path segments are ignored.
+impl FromStr for PullUrl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(s)?;
+        let path = url.path_segments()
+            .ok_or(Error::NoPath)?
+            .collect::<Vec<_>>();
+
+        if path.len() < 2 {
+            return Err(Error::NoOwnerRepo);
+        }
+
+        let number = match path.get(2..4) {
+            Some(["pull", number]) => number,
+            _ => return Err(Error::NoPull),
+        };
+
+        Ok(PullUrl {
+            host: url.host_str()
+                .ok_or(Error::NoHost)?
+                .to_owned(),
+            owner: path[0].to_owned(),
+            repo: path[1].to_owned(),
+            number: (*number).to_owned(),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggestion_url_parses_github_and_gitea_urls() {
+        let github: SuggestionUrl = "https://github.com/teddywing/git-suggestion/pull/1#discussion_r459691747"
+            .parse()
+            .unwrap();
+
+        assert_eq!(github.owner, "teddywing");
+        assert_eq!(github.repo, "git-suggestion");
+        assert_eq!(github.target, Target::Comment("459691747".to_owned()));
+        assert_eq!(github.forge, Forge::Github);
+
+        let gitea: SuggestionUrl = "http://git.example.com:3000/teddywing/git-suggestion/pulls/1#issuecomment-1234"
+            .parse()
+            .unwrap();
+
+        assert_eq!(gitea.target, Target::Comment("1234".to_owned()));
+        assert_eq!(
+            gitea.forge,
+            Forge::Gitea {
+                base_url: "http://git.example.com:3000".to_owned(),
+                pull: "1".to_owned(),
+            },
+        );
+    }
+
+    #[test]
+    fn suggestion_url_parses_review_url() {
+        let review: SuggestionUrl = "https://github.com/teddywing/git-suggestion/pull/1#pullrequestreview-462370958"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            review.target,
+            Target::Review {
+                pull: "1".to_owned(),
+                id: "462370958".to_owned(),
+            },
+        );
+        assert_eq!(review.forge, Forge::Github);
+    }
+
+    #[test]
+    fn pull_url_parses_pull_request_url() {
+        let pull: PullUrl = "https://github.com/teddywing/git-suggestion/pull/1/files#discussion_r459691747"
+            .parse()
+            .unwrap();
+
+        assert_eq!(pull.owner, "teddywing");
+        assert_eq!(pull.repo, "git-suggestion");
+        assert_eq!(pull.number, "1");
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+
+use std::process;
+
+use github_suggestion::client;
+
+use git_suggestion::config;
+use git_suggestion::error::{eprint_error, Error};
+
+
+/// Print `error` and exit with the status that corresponds to it.
+///
+/// Help and the version aren't failures, and are printed to standard output.
+pub fn fail(error: Error) -> ! {
+    match &error {
+        Error::Config(config::Error::Help(usage)) => {
+            print!("{}", usage);
+
+            process::exit(exitcode::USAGE);
+        },
+        Error::Config(config::Error::Version) => {
+            println!("{}", error);
+
+            process::exit(exitcode::OK);
+        },
+        Error::Config(config::Error::Usage(usage)) => {
+            eprint!("{}", usage);
+
+            process::exit(exitcode::USAGE);
+        },
+        _ => {
+            eprint_error(&error);
+
+            process::exit(exit_code(&error));
+        },
+    }
+}
+
+/// Get the process exit code that corresponds to `error`.
+///
+/// For `Error::Multiple`, this is the exit code of the first error.
+fn exit_code(error: &Error) -> exitcode::ExitCode {
+    match error {
+        Error::Regex(_) => exitcode::SOFTWARE,
+        Error::Branch(_) => exitcode::UNAVAILABLE,
+        Error::Commit(_) => exitcode::UNAVAILABLE,
+        Error::Config(_) => exitcode::CONFIG,
+        Error::Current(_) => exitcode::UNAVAILABLE,
+        Error::Lsp(_) => exitcode::PROTOCOL,
+        Error::OwnerRepo(_) => exitcode::CONFIG,
+        Error::Url(_) => exitcode::USAGE,
+        Error::Client(client::Error::Deserialize(_)) => exitcode::SOFTWARE,
+        Error::Client(_) => exitcode::UNAVAILABLE,
+        Error::Suggestion(_) => exitcode::UNAVAILABLE,
+        Error::FormatPatch(_) => exitcode::UNAVAILABLE,
+        Error::Fixup(_) => exitcode::UNAVAILABLE,
+        Error::Push(_) => exitcode::UNAVAILABLE,
+        Error::Quickfix(_) => exitcode::UNAVAILABLE,
+        Error::Revert(_) => exitcode::UNAVAILABLE,
+        Error::Status(_) => exitcode::UNAVAILABLE,
+        Error::Io { .. } => exitcode::UNAVAILABLE,
+        Error::Multiple(errors) => errors.first()
+            .map(exit_code)
+            .unwrap_or(exitcode::SOFTWARE),
+    }
+}
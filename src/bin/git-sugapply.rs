@@ -17,37 +17,163 @@
 use std::env;
 use std::process;
 
-use exitcode;
-
-use git_suggestion::{gseprintln, for_suggestion};
+use getopts::Options;
+use git_suggestion::{gseprintln, for_suggestion, suggestions};
+use git_suggestion::branch::commit_to_branch_with_repo;
+use git_suggestion::commit::{self, commit_with_repo};
 use git_suggestion::config::Config;
+use git_suggestion::error::Error;
+use git_suggestion::fixup::fixup_with_repo;
+use git_suggestion::push::push_with_repo;
+use git_suggestion::revert::revert_with_repo;
+
+mod exit;
 
 
 fn main() {
     let args: Vec<_> = env::args().collect();
 
-    let config = match Config::get(
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "branch",
+        "commit the suggestions to BRANCH without checking it out",
+        "BRANCH",
+    );
+    opts.optflag(
+        "",
+        "commit",
+        "apply the suggestions and commit them together, crediting their \
+            authors as co-authors",
+    );
+    opts.optflag(
+        "",
+        "fixup",
+        "commit each suggestion as a fixup of the branch commit that \
+            introduced its lines",
+    );
+    opts.optflag(
+        "",
+        "push",
+        "with --commit or --branch, push the commit to the pull request branch",
+    );
+    opts.optflag(
+        "R",
+        "reverse",
+        "undo suggestions that were applied to the working tree",
+    );
+
+    let (config, opt_matches) = match Config::get_with_current(
         &args,
-        "usage: git sugapply [options] <suggestion>...",
+        "usage: git sugapply [options] [<suggestion>...]",
+        opts,
     ) {
         Ok(c) => c,
-        Err(e) => {
-            gseprintln!(e);
+        Err(e) => exit::fail(e.into()),
+    };
+
+    let branch = opt_matches.opt_str("branch");
+    let mut is_commit = opt_matches.opt_present("commit");
+    let mut is_fixup = opt_matches.opt_present("fixup");
+    let is_reverse = opt_matches.opt_present("reverse");
+    let is_push = opt_matches.opt_present("push");
+
+    let modes = [branch.is_some(), is_commit, is_fixup, is_reverse];
+
+    match modes.iter().filter(|&&m| m).count() {
+        0 => match config.apply_mode.as_deref() {
+            None | Some("apply") => (),
+            Some("commit") => is_commit = true,
+            Some("fixup") => is_fixup = true,
+            Some(mode) => {
+                gseprintln!(format!("unknown apply mode '{}'", mode));
 
-            process::exit(exitcode::CONFIG);
+                process::exit(exitcode::CONFIG);
+            },
         },
+        1 => (),
+        _ => {
+            gseprintln!(
+                "--branch, --commit, --fixup and --reverse cannot be used \
+                    together"
+            );
+
+            process::exit(exitcode::USAGE);
+        },
+    }
+
+    if is_push && !(branch.is_some() || is_commit) {
+        gseprintln!("--push requires --commit or --branch");
+
+        process::exit(exitcode::USAGE);
+    }
+
+    let result = if branch.is_some() || is_commit {
+        commit_all(&config, branch.as_deref(), is_push)
+    } else {
+        apply_each(&config, is_fixup, is_reverse)
     };
 
+    if let Err(e) = result {
+        exit::fail(e);
+    }
+}
+
+/// Apply all suggestions and commit them together, either on `HEAD` or on
+/// `branch`. If `is_push` is set, push the commit to the pull request branch.
+fn commit_all(
+    config: &Config,
+    branch: Option<&str>,
+    is_push: bool,
+) -> Result<(), Error> {
+    let repo = config.repository()?;
+    let suggestions = suggestions(config).into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let template = config.commit_template
+        .as_deref()
+        .unwrap_or(commit::DEFAULT_TEMPLATE);
+
+    match branch {
+        Some(branch) => {
+            commit_to_branch_with_repo(&suggestions, template, branch, &repo)?;
+        },
+        None => {
+            commit_with_repo(&suggestions, template, &repo)?;
+        },
+    }
+
+    if is_push {
+        push_with_repo(
+            &suggestions,
+            branch.unwrap_or("HEAD"),
+            config.require_github_token()?,
+            &repo,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Apply, fix up, or revert each suggestion in turn.
+fn apply_each(
+    config: &Config,
+    is_fixup: bool,
+    is_reverse: bool,
+) -> Result<(), Error> {
+    let repo = config.repository()?;
+
     for_suggestion(
-        &config,
+        config,
         |suggestion| {
-            match suggestion.apply() {
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::UNAVAILABLE);
-                },
-                _ => (),
+            if is_fixup {
+                fixup_with_repo(suggestion, &repo)?;
+            } else if is_reverse {
+                revert_with_repo(suggestion, &repo)?;
+            } else {
+                suggestion.apply_with_repo(&repo)?;
             }
+
+            Ok(())
         },
-    );
+    )
 }
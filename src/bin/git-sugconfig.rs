@@ -0,0 +1,66 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::env;
+use std::process;
+
+use getopts::Options;
+use git_suggestion::config::Config;
+
+mod exit;
+
+
+/// Settings whose values are replaced with this in the output.
+const SECRETS: [&str; 2] = ["github-token", "gitea-token"];
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optflag(
+        "l",
+        "list",
+        "list the effective configuration values and where each came from",
+    );
+
+    let usage_brief = "usage: git sugconfig --list [options]";
+
+    let (config, opt_matches) = match Config::get_without_suggestions(
+        &args,
+        usage_brief,
+        opts,
+    ) {
+        Ok(c) => c,
+        Err(e) => exit::fail(e.into()),
+    };
+
+    if !opt_matches.opt_present("list") {
+        eprintln!("{}", usage_brief);
+
+        process::exit(exitcode::USAGE);
+    }
+
+    for setting in &config.settings {
+        let value = if SECRETS.contains(&setting.name) {
+            "<redacted>"
+        } else {
+            &setting.value
+        };
+
+        println!("{}\t{}={}", setting.origin, setting.name, value);
+    }
+}
@@ -15,66 +15,58 @@
 
 
 use std::env;
-use std::process;
 use std::process::Command;
 
-use exitcode;
-
-use git_suggestion::{gseprintln, for_suggestion};
+use getopts::Options;
+use git_suggestion::for_suggestion;
 use git_suggestion::config::Config;
+use git_suggestion::error::Error;
 use git_suggestion::diff_options;
 
+mod exit;
+
 
 fn main() {
     let args: Vec<_> = env::args().collect();
 
     let (args, diff_args) = diff_options::parse(&args);
 
-    let config = match Config::get(
+    let (config, _) = match Config::get_with_current(
         &args,
-        "usage: git sugdiff [options] <suggestion>...",
+        "usage: git sugdiff [options] [<suggestion>...]",
+        Options::new(),
     ) {
         Ok(c) => c,
-        Err(e) => {
-            gseprintln!(e);
-
-            process::exit(exitcode::CONFIG);
-        },
+        Err(e) => exit::fail(e.into()),
     };
 
-    for_suggestion(
-        &config,
-        |suggestion| {
-            let blob = match suggestion.blob() {
-                Ok(b) => b,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::UNAVAILABLE);
-                },
-            };
+    let result = config.repository()
+        .map_err(Error::from)
+        .and_then(|repo| for_suggestion(
+            &config,
+            |suggestion| {
+                let blob = suggestion.blob_with_repo(&repo)?;
+
+                Command::new("git")
+                    .arg("--git-dir")
+                    .arg(repo.path())
+                    .arg("--no-pager")
+                    .arg("diff")
+                    .args(&diff_args)
+                    .arg(format!("{}:{}", suggestion.commit, suggestion.path))
+                    .arg(blob.to_string())
+                    .spawn()
+                    .and_then(|mut child| child.wait())
+                    .map_err(|e| Error::Io {
+                        source: e,
+                        message: "Unable to run git-diff".to_owned(),
+                    })?;
 
-            let mut child = match Command::new("git")
-                .arg("--no-pager")
-                .arg("diff")
-                .args(&diff_args)
-                .arg(format!("{}:{}", suggestion.commit(), suggestion.path()))
-                .arg(blob.to_string())
-                .spawn()
-            {
-                Ok(c) => c,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::UNAVAILABLE);
-                },
-            };
+                Ok(())
+            },
+        ));
 
-            match child.wait() {
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::UNAVAILABLE);
-                },
-                _ => (),
-            };
-        },
-    );
+    if let Err(e) = result {
+        exit::fail(e);
+    }
 }
@@ -0,0 +1,58 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::env;
+use std::io::{self, Write};
+
+use git_suggestion::for_suggestion;
+use git_suggestion::config::Config;
+use git_suggestion::error::Error;
+use git_suggestion::format_patch::format_patch_with_repo;
+
+mod exit;
+
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+
+    let config = match Config::get(
+        &args,
+        "usage: git sugformat-patch [options] <suggestion>...",
+    ) {
+        Ok(c) => c,
+        Err(e) => exit::fail(e.into()),
+    };
+
+    let result = config.repository()
+        .map_err(Error::from)
+        .and_then(|repo| for_suggestion(
+            &config,
+            |suggestion| {
+                io::stdout()
+                    .write_all(&format_patch_with_repo(suggestion, &repo)?)
+                    .map_err(|e| Error::Io {
+                        source: e,
+                        message: "Unable to write patch".to_owned(),
+                    })?;
+
+                Ok(())
+            },
+        ));
+
+    if let Err(e) = result {
+        exit::fail(e);
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::env;
+use std::io::{self, BufReader};
+
+use getopts::Options;
+
+use git_suggestion::config::Config;
+use git_suggestion::error::Error;
+use git_suggestion::lsp::{self, PullRequestSource};
+
+mod exit;
+
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+
+    let (config, _) = match Config::get_without_suggestions(
+        &args,
+        "usage: git-suggestion-lsp [options]",
+        Options::new(),
+    ) {
+        Ok(c) => c,
+        Err(e) => exit::fail(e.into()),
+    };
+
+    let repo = match config.repository() {
+        Ok(r) => r,
+        Err(e) => exit::fail(e.into()),
+    };
+
+    let (connection, io_threads) = lsp::connect(
+        BufReader::new(io::stdin()),
+        io::stdout(),
+    );
+
+    let result = lsp::run(
+        &connection,
+        &repo,
+        &PullRequestSource::new(&config, &repo),
+    )
+        .map_err(Error::from);
+
+    // Close the connection so the writer thread can finish.
+    drop(connection);
+
+    let result = result.and_then(|_| {
+        io_threads.join()
+            .map_err(|e| Error::Io {
+                source: e,
+                message: "Unable to communicate with the client".to_owned(),
+            })
+    });
+
+    if let Err(e) = result {
+        exit::fail(e);
+    }
+}
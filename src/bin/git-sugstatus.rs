@@ -0,0 +1,96 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::env;
+use std::process;
+
+use getopts::Options;
+use git_suggestion::{gseprintln, pull_suggestions};
+use git_suggestion::config::Config;
+use git_suggestion::error::Error;
+use git_suggestion::quickfix::quickfix_with_repo;
+use git_suggestion::status::status_with_repo;
+
+mod exit;
+
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt(
+        "",
+        "format",
+        "output format, \"status\" (default) or \"quickfix\"",
+        "FORMAT",
+    );
+
+    let (config, opt_matches) = match Config::get_with_options(
+        &args,
+        "usage: git sugstatus [options] <pull-request>...",
+        opts,
+    ) {
+        Ok(c) => c,
+        Err(e) => exit::fail(e.into()),
+    };
+
+    let format = opt_matches.opt_str("format")
+        .or_else(|| config.format.clone());
+
+    let is_quickfix = match format.as_deref() {
+        None | Some("status") => false,
+        Some("quickfix") => true,
+        Some(format) => {
+            gseprintln!(format!("unknown format '{}'", format));
+
+            process::exit(exitcode::USAGE);
+        },
+    };
+
+    let result = config.repository()
+        .map_err(Error::from)
+        .and_then(|repo| Ok((repo, pull_suggestions(&config)?)))
+        .and_then(|(repo, suggestions)| {
+            for suggestion in suggestions {
+                if is_quickfix {
+                    if let Some(line) = quickfix_with_repo(&suggestion, &repo)? {
+                        println!("{}", line);
+                    }
+
+                    continue;
+                }
+
+                let state = status_with_repo(&suggestion, &repo)
+                    .map_err(Error::from)?;
+
+                println!(
+                    "{:<9} {}:{}-{} {}",
+                    state,
+                    suggestion.path,
+                    suggestion.start_line,
+                    suggestion.end_line,
+                    suggestion.url.as_ref().unwrap_or(&suggestion.id),
+                );
+            }
+
+            Ok(())
+        });
+
+    if let Err(e) = result {
+        exit::fail(e);
+    }
+}
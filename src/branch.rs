@@ -0,0 +1,388 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::path::Path;
+use std::process::Command;
+
+use git2::{Oid, Repository, Tree};
+use thiserror::Error;
+
+use github_suggestion::{suggestion, Suggestion};
+use github_suggestion::suggestion::Location;
+
+use crate::commit;
+
+
+/// Errors committing suggestions to a branch.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Suggestion(#[from] suggestion::Error),
+
+    #[error("'{0}' is not a branch")]
+    NotABranch(String),
+
+    #[error("Branch '{0}' is checked out, apply the suggestions without --branch")]
+    CheckedOut(String),
+
+    #[error("Lines {start}-{end} of {path} have changed on the branch")]
+    Conflict {
+        path: String,
+        start: usize,
+        end: usize,
+    },
+
+    #[error("The suggestions are already on the branch")]
+    NoChanges,
+
+    #[error("Branch '{0}' was updated while applying the suggestions")]
+    NotFastForward(String),
+
+    #[error("{message}: {source}")]
+    Io {
+        source: std::io::Error,
+        message: String,
+    },
+
+    #[error("git-worktree failed to list the worktrees")]
+    WorktreeList,
+}
+
+
+/// Apply `suggestions` on top of `branch` and commit them to it in the
+/// repository in the current environment.
+///
+/// Returns the ID of the new commit.
+pub fn commit_to_branch(
+    suggestions: &[Suggestion],
+    template: &str,
+    branch: &str,
+) -> Result<Oid, Error> {
+    let repo = Repository::open_from_env()?;
+
+    commit_to_branch_with_repo(suggestions, template, branch, &repo)
+}
+
+/// Apply `suggestions` on top of `branch` and commit them to it in `repo`,
+/// without touching the working tree or the index.
+///
+/// Each suggestion is located in the branch's version of its file, and its
+/// new blob is written into a tree built on the branch's tree. Suggestions
+/// that are already on the branch are skipped. The branch is only updated if
+/// it still points at the commit the suggestions were applied to. The commit
+/// message is built from `template` as with `commit::commit()`.
+///
+/// A branch that is checked out in any worktree of `repo` is refused.
+pub fn commit_to_branch_with_repo(
+    suggestions: &[Suggestion],
+    template: &str,
+    branch: &str,
+    repo: &Repository,
+) -> Result<Oid, Error> {
+    let reference = repo.resolve_reference_from_short_name(branch)?;
+
+    if !reference.is_branch() {
+        return Err(Error::NotABranch(branch.to_owned()));
+    }
+
+    let name = reference.name()
+        .ok_or_else(|| Error::NotABranch(branch.to_owned()))?
+        .to_owned();
+
+    if is_checked_out(repo, &name)? {
+        return Err(Error::CheckedOut(branch.to_owned()));
+    }
+
+    let parent = reference.peel_to_commit()?;
+    let mut tree = parent.tree()?;
+    let mut applied = Vec::new();
+
+    for suggestion in suggestions {
+        let path = Path::new(&suggestion.path);
+        let entry = tree.get_path(path)?;
+        let blob = entry.to_object(repo)?.peel_to_blob()?;
+
+        let line = match suggestion.locate(repo, blob.content())? {
+            Location::Original(line) => line,
+            Location::Applied(_) => continue,
+            Location::Missing => return Err(Error::Conflict {
+                path: suggestion.path.clone(),
+                start: suggestion.start_line,
+                end: suggestion.end_line,
+            }),
+        };
+
+        // Move the suggestion onto the tree built so far.
+        let relocated = Suggestion {
+            commit: tree.id().to_string(),
+            start_line: line,
+            end_line: line + suggestion.end_line - suggestion.start_line,
+            ..suggestion.clone()
+        };
+
+        let new_blob = relocated.blob_with_repo(repo)?;
+        let components: Vec<_> = suggestion.path.split('/').collect();
+        let tree_id = update_tree(
+            repo,
+            &tree,
+            &components,
+            new_blob,
+            entry.filemode(),
+        )?;
+
+        tree = repo.find_tree(tree_id)?;
+        applied.push(suggestion.clone());
+    }
+
+    if applied.is_empty() {
+        return Err(Error::NoChanges);
+    }
+
+    let signature = repo.signature()?;
+    let id = repo.commit(
+        None,
+        &signature,
+        &signature,
+        &commit::message(template, &applied),
+        &tree,
+        &[&parent],
+    )?;
+
+    let log_message = format!(
+        "sugapply: {}",
+        template.lines().next().unwrap_or_default(),
+    );
+
+    match repo.reference_matching(&name, id, true, parent.id(), &log_message) {
+        Ok(_) => Ok(id),
+        Err(e) if e.code() == git2::ErrorCode::Modified =>
+            Err(Error::NotFastForward(branch.to_owned())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write a copy of `tree` with the file at `path`, given as path
+/// components, set to `blob`, and get the new tree's ID.
+fn update_tree(
+    repo: &Repository,
+    tree: &Tree<'_>,
+    path: &[&str],
+    blob: Oid,
+    filemode: i32,
+) -> Result<Oid, Error> {
+    let mut builder = repo.treebuilder(Some(tree))?;
+
+    match path {
+        [name] => {
+            builder.insert(name, blob, filemode)?;
+        },
+        [dir, rest @ ..] => {
+            let subtree = tree.get_name(dir)
+                .ok_or_else(|| git2::Error::from_str(
+                    &format!("'{}' is not in the tree", dir),
+                ))?
+                .to_object(repo)?
+                .peel_to_tree()?;
+
+            let subtree_id = update_tree(repo, &subtree, rest, blob, filemode)?;
+
+            builder.insert(dir, subtree_id, 0o040000)?;
+        },
+        [] => unreachable!("path has at least one component"),
+    }
+
+    Ok(builder.write()?)
+}
+
+/// Check whether the branch with the full reference `name` is checked out in
+/// any worktree of `repo`.
+fn is_checked_out(repo: &Repository, name: &str) -> Result<bool, Error> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["worktree", "list", "--porcelain"])
+        .output()
+        .map_err(|e| Error::Io {
+            source: e,
+            message: "Unable to run git-worktree".to_owned(),
+        })?;
+
+    if !output.status.success() {
+        return Err(Error::WorktreeList);
+    }
+
+    let branch = format!("branch {}", name);
+
+    Ok(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line == branch)
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use github_suggestion::{Author, Side};
+    use tempfile::tempdir;
+
+    const POEM: &str = "’Twas brillig, and the slithy toves\n\
+        Did gyre and gimble in the wabe:\n\
+        All mimsy were the borogoves,\n\
+        And the mome raths outgrabe.\n\
+        Beware the Jabberwock, my son!\n";
+
+    /// Commit `content` at `path` on top of `parents` without updating any
+    /// reference.
+    fn commit(
+        repo: &Repository,
+        path: &str,
+        content: &str,
+        parents: &[&git2::Commit<'_>],
+    ) -> Oid {
+        let mut index = git2::Index::new().unwrap();
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        index.add(&git2::IndexEntry {
+            ctime: git2::IndexTime::new(0, 0),
+            mtime: git2::IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            file_size: content.len() as u32,
+            id: blob,
+            flags: 0,
+            flags_extended: 0,
+            path: path.as_bytes().to_vec(),
+        }).unwrap();
+        let tree_id = index.write_tree_to(repo).unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+
+        let signature = repo.signature().unwrap();
+
+        repo.commit(None, &signature, &signature, "Add poem", &tree, parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn commit_to_branch_with_repo_commits_without_checkout() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Araragi Koyomi").unwrap();
+        config.set_str("user.email", "araragi@example.com").unwrap();
+
+        let path = "poems/Jabberwocky.txt";
+        let base = commit(&repo, path, POEM, &[]);
+        let base_commit = repo.find_commit(base).unwrap();
+
+        // The branch has moved on since the suggestion was made.
+        let tip = commit(
+            &repo,
+            path,
+            &format!("Jabberwocky\n\n{}", POEM),
+            &[&base_commit],
+        );
+        repo.branch("review", &repo.find_commit(tip).unwrap(), false)
+            .unwrap();
+
+        let suggestion = Suggestion {
+            id: "1".to_owned(),
+            review_id: None,
+            path: path.to_owned(),
+            commit: base.to_string(),
+            start_line: 4,
+            end_line: 4,
+            side: Side::Right,
+            message: String::new(),
+            replacement: "And the mome raths outgrabe!\n".to_owned(),
+            author: Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        };
+
+        let id = commit_to_branch_with_repo(
+            &[suggestion],
+            commit::DEFAULT_TEMPLATE,
+            "review",
+            &repo,
+        ).unwrap();
+
+        let branch = repo.find_reference("refs/heads/review").unwrap();
+        assert_eq!(branch.target(), Some(id));
+
+        let new_commit = repo.find_commit(id).unwrap();
+        assert_eq!(new_commit.parent_id(0).unwrap(), tip);
+
+        let blob = new_commit.tree().unwrap()
+            .get_path(Path::new(path)).unwrap()
+            .to_object(&repo).unwrap()
+            .peel_to_blob().unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(blob.content()).unwrap(),
+            format!("Jabberwocky\n\n{}", POEM)
+                .replace("outgrabe.", "outgrabe!"),
+        );
+
+        assert!(repo.index().unwrap().is_empty());
+    }
+
+    #[test]
+    fn commit_to_branch_with_repo_refuses_branch_in_other_worktree() {
+        let git_root = tempdir().unwrap();
+        let repo_dir = git_root.path().join("repo");
+        let repo = Repository::init(&repo_dir).unwrap();
+
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Araragi Koyomi").unwrap();
+        config.set_str("user.email", "araragi@example.com").unwrap();
+
+        let base = commit(&repo, "poems/Jabberwocky.txt", POEM, &[]);
+        repo.branch("review", &repo.find_commit(base).unwrap(), false)
+            .unwrap();
+
+        let status = Command::new("git")
+            .current_dir(&repo_dir)
+            .args(["worktree", "add", "--quiet", "../review", "review"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        match commit_to_branch_with_repo(
+            &[],
+            commit::DEFAULT_TEMPLATE,
+            "review",
+            &repo,
+        ) {
+            Err(Error::CheckedOut(branch)) => assert_eq!(branch, "review"),
+            r => panic!("expected the branch to be checked out, got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,393 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use git2::{Oid, Repository};
+use git2::build::CheckoutBuilder;
+use thiserror::Error;
+
+use github_suggestion::{suggestion, Author, Suggestion};
+
+
+/// The commit message used when no template is configured.
+pub const DEFAULT_TEMPLATE: &str = "Apply suggestions from code review";
+
+/// Errors committing suggestions.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Suggestion(#[from] suggestion::Error),
+
+    #[error("The index has staged changes")]
+    StagedChanges,
+
+    #[error("No suggestions to commit")]
+    NoSuggestions,
+}
+
+
+/// Apply `suggestions` and commit them together on `HEAD` in the repository
+/// in the current environment.
+///
+/// Returns the ID of the new commit.
+pub fn commit(
+    suggestions: &[Suggestion],
+    template: &str,
+) -> Result<Oid, Error> {
+    let repo = Repository::open_from_env()?;
+
+    commit_with_repo(suggestions, template, &repo)
+}
+
+/// Apply `suggestions` and commit them together on `HEAD` in `repo`.
+///
+/// The commit message is `template`, followed by a `Co-authored-by` trailer
+/// for each distinct suggestion author. The suggestions are applied to both
+/// the index and the working tree, so the index must not have other staged
+/// changes. Suggestions are moved onto the lines changed by the ones before
+/// them. If a suggestion fails to apply, the files changed by the ones before
+/// it are restored from `HEAD`.
+pub fn commit_with_repo(
+    suggestions: &[Suggestion],
+    template: &str,
+    repo: &Repository,
+) -> Result<Oid, Error> {
+    if suggestions.is_empty() {
+        return Err(Error::NoSuggestions);
+    }
+
+    let head_commit = repo.head()?.peel_to_commit()?;
+
+    let mut index = repo.index()?;
+    let staged = repo.diff_tree_to_index(
+        Some(&head_commit.tree()?),
+        Some(&index),
+        None,
+    )?;
+
+    if staged.deltas().len() > 0 {
+        return Err(Error::StagedChanges);
+    }
+
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        if let Err(e) = suggestion.apply_at(repo, git2::ApplyLocation::Both) {
+            let mut checkout = CheckoutBuilder::new();
+            checkout.force();
+
+            for applied in &suggestions[..i] {
+                checkout.path(&applied.path);
+            }
+
+            if i > 0 {
+                repo.checkout_head(Some(&mut checkout))?;
+            }
+
+            return Err(e.into());
+        }
+    }
+
+    index.read(true)?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let signature = repo.signature()?;
+
+    Ok(
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message(template, suggestions),
+            &tree,
+            &[&head_commit],
+        )?
+    )
+}
+
+/// Build a commit message from `template` with a `Co-authored-by` trailer
+/// for each distinct author of `suggestions`, in the order they first
+/// appear.
+///
+/// In the template, `{count}` is replaced with the number of suggestions,
+/// `{files}` with the files they change, and `{authors}` with the logins of
+/// their authors. Authors without an email address are credited with a
+/// no-reply address.
+pub fn message(template: &str, suggestions: &[Suggestion]) -> String {
+    let mut authors: Vec<Author> = Vec::new();
+    let mut files: Vec<&str> = Vec::new();
+
+    for suggestion in suggestions {
+        let author = Author {
+            name: suggestion.author.name.clone(),
+            email: suggestion.author_email(),
+        };
+
+        if !authors.contains(&author) {
+            authors.push(author);
+        }
+
+        if !files.contains(&suggestion.path.as_str()) {
+            files.push(&suggestion.path);
+        }
+    }
+
+    let logins: Vec<&str> = authors.iter()
+        .map(|a| a.name.as_str())
+        .collect();
+
+    let mut message = template
+        .replace("{count}", &suggestions.len().to_string())
+        .replace("{files}", &files.join(", "))
+        .replace("{authors}", &logins.join(", "))
+        .trim_end()
+        .to_owned();
+    message.push('\n');
+
+    if !authors.is_empty() {
+        message.push('\n');
+    }
+
+    for author in authors {
+        message.push_str(
+            &format!("Co-authored-by: {} <{}>\n", author.name, author.email),
+        );
+    }
+
+    message
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    use github_suggestion::Side;
+    use tempfile::{tempdir, TempDir};
+
+    const POEM: &str = "’Twas brillig, and the slithy toves\n\
+        Did gyre and gimble in the wabe:\n\
+        All mimsy were the borogoves,\n\
+        And the mome raths outgrabe.\n\
+        Beware the Jabberwock, my son!\n\
+        The jaws that bite, the claws that catch!\n\
+        Beware the Jubjub bird, and shun\n\
+        The frumious Bandersnatch!\n\
+        He took his vorpal sword in hand:\n";
+
+    fn suggestion(
+        commit: Oid,
+        line: usize,
+        replacement: &str,
+        login: &str,
+    ) -> Suggestion {
+        Suggestion {
+            id: line.to_string(),
+            review_id: None,
+            path: "Jabberwocky.txt".to_owned(),
+            commit: commit.to_string(),
+            start_line: line,
+            end_line: line,
+            side: Side::Right,
+            message: String::new(),
+            replacement: replacement.to_owned(),
+            author: Author {
+                name: login.to_owned(),
+                email: format!("{}@users.noreply.github.com", login),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        }
+    }
+
+    /// Create a repository with `POEM` committed at `Jabberwocky.txt`.
+    fn poem_repo() -> (TempDir, Repository, Oid) {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Araragi Koyomi").unwrap();
+        config.set_str("user.email", "araragi@example.com").unwrap();
+
+        fs::write(
+            git_root.path().join("Jabberwocky.txt"),
+            POEM,
+        ).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("Jabberwocky.txt")).unwrap();
+        index.write().unwrap();
+
+        let base = {
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = repo.signature().unwrap();
+
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "Add poem",
+                &tree,
+                &[],
+            ).unwrap()
+        };
+
+        (git_root, repo, base)
+    }
+
+    #[test]
+    fn commit_with_repo_credits_each_author_once() {
+        let (_git_root, repo, base) = poem_repo();
+
+        let suggestions = vec![
+            suggestion(base, 1, "’Twas brillig!\n", "oshino"),
+            suggestion(base, 9, "He took his vorpal sword!\n", "senjougahara"),
+            suggestion(base, 5, "Beware the Jabberwock!\n", "oshino"),
+        ];
+
+        let id = commit_with_repo(&suggestions, DEFAULT_TEMPLATE, &repo)
+            .unwrap();
+        let commit = repo.find_commit(id).unwrap();
+
+        assert_eq!(
+            commit.message().unwrap(),
+            "Apply suggestions from code review\n\
+                \n\
+                Co-authored-by: oshino <oshino@users.noreply.github.com>\n\
+                Co-authored-by: senjougahara <senjougahara@users.noreply.github.com>\n",
+        );
+
+        let blob = commit.tree().unwrap()
+            .get_path(Path::new("Jabberwocky.txt")).unwrap()
+            .to_object(&repo).unwrap()
+            .peel_to_blob().unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(blob.content()).unwrap(),
+            "’Twas brillig!\n\
+                Did gyre and gimble in the wabe:\n\
+                All mimsy were the borogoves,\n\
+                And the mome raths outgrabe.\n\
+                Beware the Jabberwock!\n\
+                The jaws that bite, the claws that catch!\n\
+                Beware the Jubjub bird, and shun\n\
+                The frumious Bandersnatch!\n\
+                He took his vorpal sword!\n",
+        );
+    }
+
+    #[test]
+    fn message_fills_in_template_and_credits_authors_without_email() {
+        let suggestions = vec![
+            suggestion(Oid::zero(), 1, "’Twas brillig!\n", "oshino"),
+            Suggestion {
+                path: "Hunting of the Snark.txt".to_owned(),
+                author: Author {
+                    name: "hanekawa".to_owned(),
+                    email: String::new(),
+                },
+                ..suggestion(Oid::zero(), 1, "Just the place!\n", "hanekawa")
+            },
+            suggestion(Oid::zero(), 5, "Beware the Jabberwock!\n", "oshino"),
+        ];
+
+        assert_eq!(
+            message(
+                "Apply {count} suggestions to {files}\n\nBy {authors}.",
+                &suggestions,
+            ),
+            "Apply 3 suggestions to Jabberwocky.txt, Hunting of the Snark.txt\n\
+                \n\
+                By oshino, hanekawa.\n\
+                \n\
+                Co-authored-by: oshino <oshino@users.noreply.github.com>\n\
+                Co-authored-by: hanekawa <hanekawa@users.noreply.github.com>\n",
+        );
+    }
+
+    #[test]
+    fn commit_with_repo_moves_suggestions_onto_earlier_ones() {
+        let (_git_root, repo, base) = poem_repo();
+
+        let suggestions = vec![
+            suggestion(
+                base,
+                2,
+                "Did gyre and gimble\nin the wabe:\n",
+                "oshino",
+            ),
+            suggestion(base, 4, "And the mome raths outgrabe!\n", "oshino"),
+            suggestion(base, 9, "He took his vorpal sword!\n", "oshino"),
+        ];
+
+        let id = commit_with_repo(&suggestions, DEFAULT_TEMPLATE, &repo)
+            .unwrap();
+
+        let blob = repo.find_commit(id).unwrap()
+            .tree().unwrap()
+            .get_path(Path::new("Jabberwocky.txt")).unwrap()
+            .to_object(&repo).unwrap()
+            .peel_to_blob().unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(blob.content()).unwrap(),
+            "’Twas brillig, and the slithy toves\n\
+                Did gyre and gimble\n\
+                in the wabe:\n\
+                All mimsy were the borogoves,\n\
+                And the mome raths outgrabe!\n\
+                Beware the Jabberwock, my son!\n\
+                The jaws that bite, the claws that catch!\n\
+                Beware the Jubjub bird, and shun\n\
+                The frumious Bandersnatch!\n\
+                He took his vorpal sword!\n",
+        );
+    }
+
+    #[test]
+    fn commit_with_repo_undoes_suggestions_on_failure() {
+        let (git_root, repo, base) = poem_repo();
+        let tree = repo.find_commit(base).unwrap().tree().unwrap();
+
+        let suggestions = vec![
+            suggestion(base, 1, "’Twas brillig!\n’Twas!\n", "oshino"),
+            suggestion(base, 5, "Beware the Jabberwock!\n", "oshino"),
+            Suggestion {
+                path: "Hunting of the Snark.txt".to_owned(),
+                ..suggestion(base, 1, "Just the place for a Snark!\n", "oshino")
+            },
+        ];
+
+        assert!(commit_with_repo(&suggestions, DEFAULT_TEMPLATE, &repo).is_err());
+
+        assert_eq!(repo.head().unwrap().target(), Some(base));
+        assert_eq!(
+            fs::read_to_string(git_root.path().join("Jabberwocky.txt"))
+                .unwrap(),
+            POEM,
+        );
+
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        assert_eq!(index.write_tree().unwrap(), tree.id());
+    }
+}
@@ -16,22 +16,40 @@
 
 use std::env;
 use std::ffi::OsStr;
-use std::process;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::OnceLock;
 
 use getopts::{self, Options};
 use git2::{self, Repository};
 use thiserror::Error;
 
+use crate::config_file::{self, File, Settings};
+use crate::filter::{self, Filter};
 use crate::owner_repo::{self, OwnerRepo};
 use crate::VERSION;
 
 
 /// Program-specific prefix for Git config values.
-const GIT_CONFIG_PREFIX: &'static str = "githubSuggestion.";
+const GIT_CONFIG_PREFIX: &str = "githubSuggestion";
 
 /// Configuration errors.
 #[derive(Debug, Error)]
 pub enum Error {
+    /// `-h` was given. Holds the usage to print.
+    #[error("{0}")]
+    Help(String),
+
+    /// `-V` was given.
+    #[error("{}", VERSION)]
+    Version,
+
+    /// Required arguments are missing. Holds the usage to print.
+    #[error("{0}")]
+    Usage(String),
+
     #[error("Unable to parse arguments: {0}")]
     Opts(#[from] getopts::Fail),
 
@@ -41,6 +59,27 @@ pub enum Error {
         var: String,
     },
 
+    #[error("-C must come before other options, and takes a path")]
+    Directory,
+
+    #[error("No GitHub token: use --github-token, githubSuggestion.githubToken, GITHUB_TOKEN, or a token command")]
+    NoGithubToken,
+
+    #[error("No Gitea token: use --gitea-token, githubSuggestion.giteaToken, or GITEA_TOKEN")]
+    NoGiteaToken,
+
+    #[error("Token command '{command}' failed: {source}")]
+    TokenCommand {
+        source: TokenCommandError,
+        command: String,
+    },
+
+    #[error(transparent)]
+    File(#[from] config_file::Error),
+
+    #[error(transparent)]
+    Filter(#[from] filter::Error),
+
     #[error(transparent)]
     OwnerRepo(#[from] owner_repo::Error),
 
@@ -48,18 +87,157 @@ pub enum Error {
     Git(#[from] git2::Error),
 }
 
+/// Reasons that the token command failed.
+#[derive(Clone, Debug, Error)]
+pub enum TokenCommandError {
+    #[error("unable to run it: {0}")]
+    Spawn(String),
+
+    #[error("it exited with {0}")]
+    Status(process::ExitStatus),
+
+    #[error("it printed no token")]
+    Empty,
+}
+
+/// Where a configuration value came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+    /// A command line option.
+    CommandLine(String),
+
+    /// A Git config key.
+    GitConfig(String),
+
+    /// An environment variable.
+    Environment(String),
+
+    /// A section of the configuration file.
+    File {
+        path: PathBuf,
+        section: String,
+    },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::CommandLine(option) => write!(f, "command line:{}", option),
+            Origin::GitConfig(key) => write!(f, "git config:{}", key),
+            Origin::Environment(var) => write!(f, "env:{}", var),
+            Origin::File { path, section } =>
+                write!(f, "file:{} [{}]", path.display(), section),
+        }
+    }
+}
+
+/// A configuration value and where it came from.
+#[derive(Clone, Debug)]
+pub struct Setting {
+    pub name: &'static str,
+    pub value: String,
+    pub origin: Origin,
+}
+
 /// Configuration extracted from config files and command line arguments.
+#[derive(Default)]
 pub struct Config {
-    pub github_token: String,
-    pub o_r: Result<OwnerRepo, owner_repo::Error>,
+    /// The directory given with leading `-C` options, to find the repository
+    /// from instead of the current directory.
+    pub directory: Option<PathBuf>,
+
+    pub github_token: Option<String>,
+    pub gitea_token: Option<String>,
+
+    /// A command that prints the GitHub token, run when no token is set.
+    pub token_command: Option<String>,
+
+    pub remotes: Vec<String>,
+    pub keep_going: bool,
     pub suggestions: Vec<String>,
+    pub json_files: Vec<String>,
+    pub filter: Filter,
+    pub commit_template: Option<String>,
+
+    /// Use the pending suggestions on the open pull request for the current
+    /// branch.
+    pub current: bool,
+
+    /// The default `git sugstatus` output format.
+    pub format: Option<String>,
+
+    /// The default `git sugapply` mode.
+    pub apply_mode: Option<String>,
+
+    /// The configuration file, if there is one.
+    pub file: Option<File>,
+
+    /// The configured values, in the order they were read, with where each
+    /// one came from.
+    pub settings: Vec<Setting>,
+
+    /// The result of running `token_command`, once it has run.
+    pub(crate) command_token: OnceLock<Result<String, TokenCommandError>>,
 }
 
 impl Config {
     /// Set up command line arguments. Extract configuration values from command
     /// line arguments, Git config, and environment variables.
     pub fn get<S: AsRef<OsStr>>(args: &[S], usage_brief: &str) -> Result<Self, Error> {
-        let mut opts = Options::new();
+        Self::get_with_options(args, usage_brief, Options::new())
+            .map(|(config, _)| config)
+    }
+
+    /// Like `Config::get()`, but also accept the program-specific options in
+    /// `opts`. The matches are returned so the program can read them.
+    pub fn get_with_options<S: AsRef<OsStr>>(
+        args: &[S],
+        usage_brief: &str,
+        opts: Options,
+    ) -> Result<(Self, getopts::Matches), Error> {
+        Self::parse(args, usage_brief, opts, true)
+    }
+
+    /// Like `Config::get_with_options()`, but don't require suggestion
+    /// arguments, for programs that find the suggestions themselves.
+    pub fn get_without_suggestions<S: AsRef<OsStr>>(
+        args: &[S],
+        usage_brief: &str,
+        opts: Options,
+    ) -> Result<(Self, getopts::Matches), Error> {
+        Self::parse(args, usage_brief, opts, false)
+    }
+
+    /// Like `Config::get_with_options()`, but also accept `--current`, to use
+    /// the pending suggestions on the open pull request for the current
+    /// branch. This is the default when no suggestions are given.
+    pub fn get_with_current<S: AsRef<OsStr>>(
+        args: &[S],
+        usage_brief: &str,
+        mut opts: Options,
+    ) -> Result<(Self, getopts::Matches), Error> {
+        opts.optflag(
+            "",
+            "current",
+            "use the pending suggestions on the pull request for the current \
+                branch, the default without suggestion arguments",
+        );
+
+        let (mut config, opt_matches) =
+            Self::parse(args, usage_brief, opts, false)?;
+
+        config.current = opt_matches.opt_present("current")
+            || (config.suggestions.is_empty() && config.json_files.is_empty());
+
+        Ok((config, opt_matches))
+    }
+
+    fn parse<S: AsRef<OsStr>>(
+        args: &[S],
+        usage_brief: &str,
+        mut opts: Options,
+        require_suggestions: bool,
+    ) -> Result<(Self, getopts::Matches), Error> {
 
         opts.optopt(
             "",
@@ -68,100 +246,567 @@ impl Config {
             "TOKEN",
         );
         opts.optopt(
+            "",
+            "token-command",
+            "shell command that prints the GitHub API token, overriding \
+                tokens from the Git config and environment",
+            "COMMAND",
+        );
+        opts.optopt(
+            "",
+            "gitea-token",
+            "Gitea or Forgejo API token for suggestion URLs on those forges",
+            "TOKEN",
+        );
+        opts.optmulti(
             "",
             "remote",
-            "remote name, defaults to 'origin'",
+            "remote name, can be given multiple times in priority order; \
+                detected automatically by default",
             "REMOTE",
         );
+        opts.optmulti(
+            "",
+            "from-json",
+            "read review comment JSON from FILE instead of fetching suggestions",
+            "FILE",
+        );
+        opts.optmulti(
+            "C",
+            "",
+            "run as if started in PATH instead of the current directory; \
+                must come before other options",
+            "PATH",
+        );
+        opts.optopt("", "author", "only suggestions by LOGIN", "LOGIN");
+        opts.optmulti(
+            "",
+            "path",
+            "only suggestions on files matching PATHSPEC, can be given \
+                multiple times",
+            "PATHSPEC",
+        );
+        opts.optopt(
+            "",
+            "since",
+            "only suggestions created since DATE (YYYY-MM-DD or RFC 3339)",
+            "DATE",
+        );
+        opts.optopt(
+            "",
+            "review",
+            "only suggestions from the review with ID",
+            "ID",
+        );
+        opts.optflag("", "unresolved", "skip suggestions in resolved threads");
+        opts.optflag("", "not-outdated", "skip outdated suggestions");
+        opts.optflag(
+            "",
+            "keep-going",
+            "continue with the remaining suggestions after an error",
+        );
         opts.optflag("h", "help", "print this help menu");
         opts.optflag("V", "version", "show the program version");
 
-        let opt_matches = opts.parse(&args[1..])?;
+        let (directory, args) = Self::directory(&args[1..])?;
+        let opt_matches = opts.parse(args)?;
 
-        if opt_matches.opt_present("h") {
-            print_usage(&opts, usage_brief);
+        // `-C` only counts before any other argument, like Git's.
+        if opt_matches.opt_present("C") {
+            return Err(Error::Directory);
+        }
 
-            process::exit(exitcode::USAGE);
+        if opt_matches.opt_present("h") {
+            return Err(Error::Help(opts.usage(usage_brief)));
         }
 
         if opt_matches.opt_present("V") {
-            println!("{}", VERSION);
+            return Err(Error::Version);
+        }
 
-            process::exit(exitcode::OK);
+        if require_suggestions
+            && opt_matches.free.is_empty()
+            && !opt_matches.opt_present("from-json")
+        {
+            return Err(Error::Usage(opts.usage(usage_brief)));
         }
 
-        if opt_matches.free.is_empty() {
-            print_usage(&opts, usage_brief);
+        let repo = Self::open_repository(directory.as_deref())?;
+        let git_config = repo.config()?;
+        let file = File::load()?;
+
+        let mut settings = Vec::new();
+        let mut remotes = Self::remotes(&opt_matches, &git_config)?;
 
-            process::exit(exitcode::USAGE);
+        // The host and repository select the sections of the configuration
+        // file that apply.
+        let remote_names: Vec<_> = remotes.iter()
+            .map(|(r, _)| r.clone())
+            .collect();
+        let identity = OwnerRepo::from_remotes(&repo, &remote_names)
+            .ok()
+            .and_then(|o_r| o_r.into_iter().next());
+        let host = identity.as_ref().map(|o_r| o_r.host.as_str());
+        let repo_key = identity.as_ref()
+            .map(|o_r| format!("{}/{}/{}", o_r.host, o_r.owner, o_r.repo));
+
+        let from_file = |value: fn(&Settings) -> &Option<String>| {
+            file.as_ref().and_then(|f| {
+                f.get(host, repo_key.as_deref(), value)
+                    .map(|(v, section)| (
+                        v.to_owned(),
+                        Origin::File {
+                            path: f.path.clone(),
+                            section,
+                        },
+                    ))
+            })
+        };
+
+        if remotes.is_empty() {
+            remotes.extend(from_file(|s| &s.remote));
         }
 
-        let git_config = Repository::open(".")?.config()?;
+        for (remote, origin) in &remotes {
+            settings.push(Setting {
+                name: "remote",
+                value: remote.clone(),
+                origin: origin.clone(),
+            });
+        }
+
+        let mut record = |name, value: Option<(String, Origin)>| {
+            value.map(|(value, origin)| {
+                settings.push(Setting {
+                    name,
+                    value: value.clone(),
+                    origin,
+                });
+
+                value
+            })
+        };
 
-        let o_r = OwnerRepo::from_remote(
-            Self::remote(&opt_matches, &git_config)?.as_deref(),
+        let token_command_opt = Self::opt(&opt_matches, "token-command");
+
+        // A token command given on the command line overrides the tokens in
+        // the Git config and environment.
+        let github_token = match Self::opt(&opt_matches, "github-token") {
+            Some(token) => Some(token),
+            None if token_command_opt.is_some() => None,
+            None => Self::git(&git_config, "githubToken")?
+                .or(Self::env("GITHUB_TOKEN")?),
+        };
+        let github_token = record("github-token", github_token);
+        let gitea_token = record(
+            "gitea-token",
+            Self::opt(&opt_matches, "gitea-token")
+                .or(Self::git(&git_config, "giteaToken")?)
+                .or(Self::env("GITEA_TOKEN")?),
+        );
+        let token_command = record(
+            "token-command",
+            token_command_opt
+                .or(Self::git(&git_config, "tokenCommand")?)
+                .or_else(|| from_file(|s| &s.token_command)),
+        );
+        let commit_template = record(
+            "commit-template",
+            Self::git(&git_config, "commitTemplate")?
+                .or_else(|| from_file(|s| &s.commit_template)),
         );
+        let format = record("format", from_file(|s| &s.format));
+        let apply_mode = record("apply", from_file(|s| &s.apply));
 
-        Ok(Config {
-            github_token: Self::github_token(&opt_matches, &git_config)?,
-            o_r: o_r,
-            suggestions: opt_matches.free,
-        })
+        record("api-url", from_file(|s| &s.api_url));
+
+        let config = Config {
+            directory,
+            github_token,
+            gitea_token,
+            token_command,
+            remotes: remotes.into_iter().map(|(r, _)| r).collect(),
+            keep_going: opt_matches.opt_present("keep-going"),
+            json_files: opt_matches.opt_strs("from-json"),
+            suggestions: opt_matches.free.clone(),
+            filter: Filter::from_matches(&opt_matches)?,
+            commit_template,
+            current: false,
+            format,
+            apply_mode,
+            file,
+            settings,
+            command_token: OnceLock::new(),
+        };
+
+        Ok((config, opt_matches))
     }
 
-    /// Get a GitHub token, checking the following places in order:
+    /// Get the GitHub token, or an error if none was configured.
     ///
-    /// 1. Command line argument
-    /// 2. Git config
-    /// 3. Environment variable
-    fn github_token(
+    /// Without a token, the token command is run, and its output is used as
+    /// the token. The command only runs once, and its result is reused for
+    /// the rest of the process, even if it failed.
+    pub fn require_github_token(&self) -> Result<&str, Error> {
+        if let Some(token) = &self.github_token {
+            return Ok(token);
+        }
+
+        let command = self.token_command
+            .as_deref()
+            .ok_or(Error::NoGithubToken)?;
+
+        self.command_token
+            .get_or_init(|| run_token_command(command))
+            .as_deref()
+            .map_err(|e| Error::TokenCommand {
+                source: e.clone(),
+                command: command.to_owned(),
+            })
+    }
+
+    /// Get the Gitea token, or an error if none was configured.
+    pub fn require_gitea_token(&self) -> Result<&str, Error> {
+        self.gitea_token
+            .as_deref()
+            .ok_or(Error::NoGiteaToken)
+    }
+
+    /// Get the API URL for the repository `owner/repo` on the Gitea or
+    /// Forgejo server at `host` from the configuration file, if one was set.
+    ///
+    /// The repository, host and default sections are used in that order, as
+    /// for the other settings.
+    pub fn api_url(&self, host: &str, owner: &str, repo: &str) -> Option<&str> {
+        let repo_key = format!("{}/{}/{}", host, owner, repo);
+
+        self.file.as_ref()
+            .and_then(|f| f.get(Some(host), Some(&repo_key), |s| &s.api_url))
+            .map(|(url, _)| url)
+    }
+
+    /// Open the repository found from `directory`, or from the current
+    /// environment, with `GIT_DIR` and the current directory, like Git.
+    pub fn repository(&self) -> Result<Repository, Error> {
+        Ok(Self::open_repository(self.directory.as_deref())?)
+    }
+
+    /// Get the owners and repos of the candidate remotes for suggestion IDs
+    /// in the repository, in priority order.
+    pub fn owner_repos(&self) -> Result<Vec<OwnerRepo>, owner_repo::Error> {
+        let repo = Self::open_repository(self.directory.as_deref())?;
+
+        OwnerRepo::from_remotes(&repo, &self.remotes)
+    }
+
+    /// Split the leading `-C <path>` options off `args`, and get the
+    /// directory they lead to with the rest of the arguments.
+    ///
+    /// As in Git, each path is relative to the one before it.
+    fn directory<S: AsRef<OsStr>>(
+        mut args: &[S],
+    ) -> Result<(Option<PathBuf>, &[S]), Error> {
+        let mut directory: Option<PathBuf> = None;
+
+        while let Some(arg) = args.first() {
+            if arg.as_ref() != "-C" {
+                break;
+            }
+
+            let path = args.get(1).ok_or(Error::Directory)?;
+            directory.get_or_insert_with(PathBuf::new).push(path.as_ref());
+
+            args = &args[2..];
+        }
+
+        Ok((directory, args))
+    }
+
+    /// Open the repository found from `directory`, or from the current
+    /// environment if there is none.
+    fn open_repository(
+        directory: Option<&Path>,
+    ) -> Result<Repository, git2::Error> {
+        match directory {
+            Some(directory) => Repository::discover(directory),
+            None => Repository::open_from_env(),
+        }
+    }
+
+    /// Get the value of command line option `option`, if it was given.
+    fn opt(
         opt_matches: &getopts::Matches,
+        option: &str,
+    ) -> Option<(String, Origin)> {
+        opt_matches.opt_str(option)
+            .map(|v| (v, Origin::CommandLine(format!("--{}", option))))
+    }
+
+    /// Get the value of the program-specific Git config `key`, if it was set.
+    fn git(
         git_config: &git2::Config,
-    ) -> Result<String, Error> {
-        match opt_matches.opt_str("github-token") {
-            Some(t) => Ok(t),
-            None =>
-                match git_config.get_string(&git_config_key("githubToken")) {
-                    Err(e) if e.code() == git2::ErrorCode::NotFound => {
-                        let key = "GITHUB_TOKEN";
-
-                        env::var(key)
-                            .map_err(|e| Error::EnvVar {
-                                source: e,
-                                var: key.to_owned(),
-                            })
-                    },
-                    r => r.map_err(|e| Error::Git(e)),
-                },
-        }
-    }
-
-    /// Get the Git remote name from the following places in order:
+        key: &str,
+    ) -> Result<Option<(String, Origin)>, git2::Error> {
+        let key = git_config_key(key);
+
+        match git_config.get_string(&key) {
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            r => r.map(|v| Some((v, Origin::GitConfig(key)))),
+        }
+    }
+
+    /// Get the value of environment variable `var`, if it was set.
+    fn env(var: &str) -> Result<Option<(String, Origin)>, Error> {
+        match env::var(var) {
+            Ok(v) => Ok(Some((v, Origin::Environment(var.to_owned())))),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(Error::EnvVar {
+                source: e,
+                var: var.to_owned(),
+            }),
+        }
+    }
+
+    /// Get the Git remote names in priority order from the first of the
+    /// following places that has any:
     ///
-    /// 1. Command line argument
+    /// 1. Command line arguments
     /// 2. Git config
     ///
-    /// If the value wasn't set, return `Ok(None)`.
-    fn remote(
+    /// If no remotes were set, return an empty list.
+    fn remotes(
         opt_matches: &getopts::Matches,
         git_config: &git2::Config,
-    ) -> Result<Option<String>, git2::Error> {
-        match opt_matches.opt_str("remote") {
-            Some(r) => Ok(Some(r)),
-            None => match git_config.get_string(&git_config_key("remote")) {
-                Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
-                r => r.map(|r| Some(r)),
-            },
+    ) -> Result<Vec<(String, Origin)>, git2::Error> {
+        let remotes = opt_matches.opt_strs("remote");
+
+        if !remotes.is_empty() {
+            return Ok(
+                remotes.into_iter()
+                    .map(|r| (r, Origin::CommandLine("--remote".to_owned())))
+                    .collect()
+            );
         }
+
+        let key = git_config_key("remote");
+        let mut remotes = Vec::new();
+        let entries = git_config.multivar(&key, None)?;
+
+        for entry in &entries {
+            if let Some(remote) = entry?.value() {
+                remotes.push((
+                    remote.to_owned(),
+                    Origin::GitConfig(key.clone()),
+                ));
+            }
+        }
+
+        Ok(remotes)
     }
 }
 
-/// Print command line usage information to standard output.
-fn print_usage(opts: &Options, brief: &str) {
-    print!("{}", opts.usage(brief));
+/// Run the token `command` with the shell, and get the token from its
+/// standard output, without surrounding whitespace.
+///
+/// The command's standard error is that of the program, and so is its
+/// standard input when that's a terminal, so that it can prompt for a
+/// password. Otherwise standard input may carry data for the program, like
+/// the language server's messages, so the command gets none.
+fn run_token_command(command: &str) -> Result<String, TokenCommandError> {
+    let stdin = if io::stdin().is_terminal() {
+        Stdio::inherit()
+    } else {
+        Stdio::null()
+    };
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(stdin)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| TokenCommandError::Spawn(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(TokenCommandError::Status(output.status));
+    }
+
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+    if token.is_empty() {
+        return Err(TokenCommandError::Empty);
+    }
+
+    Ok(token)
 }
 
 /// Build a Git config key using the program-specific prefix and a subkey.
 fn git_config_key(key: &str) -> String {
     format!("{}.{}", GIT_CONFIG_PREFIX, key)
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn require_github_token_runs_token_command_once() {
+        let dir = tempdir().unwrap();
+        let runs = dir.path().join("runs");
+
+        let config = Config {
+            token_command: Some(
+                format!("echo run >> '{}'; echo ' token '", runs.display()),
+            ),
+            ..Config::default()
+        };
+
+        assert_eq!(config.require_github_token().unwrap(), "token");
+        assert_eq!(config.require_github_token().unwrap(), "token");
+
+        assert_eq!(fs::read_to_string(&runs).unwrap(), "run\n");
+    }
+
+    #[test]
+    fn require_github_token_reports_token_command_failure() {
+        let config = Config {
+            token_command: Some("echo token; exit 3".to_owned()),
+            ..Config::default()
+        };
+
+        match config.require_github_token() {
+            Err(Error::TokenCommand {
+                source: TokenCommandError::Status(status),
+                command,
+            }) => {
+                assert_eq!(status.code(), Some(3));
+                assert_eq!(command, "echo token; exit 3");
+            },
+            r => panic!("expected token command failure, got {:?}", r),
+        }
+
+        let config = Config {
+            token_command: Some("true".to_owned()),
+            ..Config::default()
+        };
+
+        assert!(matches!(
+            config.require_github_token(),
+            Err(Error::TokenCommand { source: TokenCommandError::Empty, .. }),
+        ));
+    }
+
+    #[test]
+    fn api_url_prefers_repo_section() {
+        let file: File = toml::from_str(
+            r#"
+            [host."codeberg.org"]
+            api-url = "https://codeberg.org/api/v1"
+
+            [repo."codeberg.org/teddywing/git-suggestion"]
+            api-url = "https://api.codeberg.example/v1"
+            "#,
+        ).unwrap();
+
+        let config = Config {
+            file: Some(file),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            config.api_url("codeberg.org", "teddywing", "git-suggestion"),
+            Some("https://api.codeberg.example/v1"),
+        );
+        assert_eq!(
+            config.api_url("codeberg.org", "teddywing", "dome-key-map"),
+            Some("https://codeberg.org/api/v1"),
+        );
+        assert_eq!(
+            config.api_url("gitea.com", "teddywing", "git-suggestion"),
+            None,
+        );
+    }
+
+    #[test]
+    fn token_command_option_overrides_configured_token() {
+        let args = ["git-sugdiff", "--token-command", "echo token", "1"];
+        let config = Config::get(&args, "").unwrap();
+
+        assert_eq!(config.github_token, None);
+        assert_eq!(config.token_command.as_deref(), Some("echo token"));
+
+        let args = [
+            "git-sugdiff",
+            "--token-command",
+            "echo token",
+            "--github-token",
+            "flag-token",
+            "1",
+        ];
+        let config = Config::get(&args, "").unwrap();
+
+        assert_eq!(config.github_token.as_deref(), Some("flag-token"));
+    }
+
+    #[test]
+    fn directory_joins_leading_c_options() {
+        let args = ["-C", "repos", "-C", "git-suggestion", "--current", "-C"];
+        let (directory, rest) = Config::directory(&args).unwrap();
+
+        assert_eq!(directory, Some(PathBuf::from("repos/git-suggestion")));
+        assert_eq!(rest, ["--current", "-C"]);
+
+        assert!(matches!(Config::directory(&["-C"]), Err(Error::Directory)));
+    }
+
+    #[test]
+    fn get_finds_repository_from_directory_option() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+        fs::create_dir(git_root.path().join("poems")).unwrap();
+
+        let poems = git_root.path().join("poems");
+        let args = [
+            "git-sugdiff",
+            "-C",
+            poems.to_str().unwrap(),
+            "--github-token",
+            "token",
+            "1",
+        ];
+        let config = Config::get(&args, "").unwrap();
+
+        assert_eq!(
+            config.repository().unwrap().path(),
+            repo.path(),
+        );
+
+        let args = ["git-sugdiff", "1", "-C", poems.to_str().unwrap()];
+        assert!(matches!(Config::get(&args, ""), Err(Error::Directory)));
+    }
+
+    #[test]
+    fn get_returns_help_version_and_usage() {
+        let usage = "usage: git sugdiff [options] <suggestion>...";
+
+        match Config::get(&["git-sugdiff", "-h"], usage) {
+            Err(Error::Help(help)) => assert!(help.starts_with(usage)),
+            r => panic!("expected help, got {:?}", r.map(|_| ())),
+        }
+
+        assert!(matches!(
+            Config::get(&["git-sugdiff", "--version"], usage),
+            Err(Error::Version),
+        ));
+
+        match Config::get(&["git-sugdiff"], usage) {
+            Err(Error::Usage(help)) => assert!(help.starts_with(usage)),
+            r => panic!("expected usage, got {:?}", r.map(|_| ())),
+        }
+    }
+}
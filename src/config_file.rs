@@ -0,0 +1,219 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+
+/// Errors reading the configuration file.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unable to read '{}': {source}", path.display())]
+    Io {
+        source: io::Error,
+        path: PathBuf,
+    },
+
+    #[error("Unable to parse '{}': {source}", path.display())]
+    Toml {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+}
+
+/// Settings that can be given in any section of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    /// The API URL of a Gitea or Forgejo host.
+    pub api_url: Option<String>,
+
+    /// A command that prints the API token.
+    pub token_command: Option<String>,
+
+    /// The default remote.
+    pub remote: Option<String>,
+
+    /// The default `git sugstatus` output format.
+    pub format: Option<String>,
+
+    /// The default `git sugapply` mode.
+    pub apply: Option<String>,
+
+    /// The commit message template for suggestion commits.
+    pub commit_template: Option<String>,
+}
+
+/// The configuration file.
+///
+/// Settings in the `[default]` section apply everywhere. They are overridden
+/// by the section for the repository's host, for example
+/// `[host."github.com"]`, which is in turn overridden by the section for the
+/// repository, for example `[repo."github.com/teddywing/git-suggestion"]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct File {
+    #[serde(skip)]
+    pub path: PathBuf,
+
+    pub default: Settings,
+    pub host: HashMap<String, Settings>,
+    pub repo: HashMap<String, Settings>,
+}
+
+impl File {
+    /// Read the configuration file at `$XDG_CONFIG_HOME/git-suggestion/
+    /// config.toml`, or `~/.config/git-suggestion/config.toml` if
+    /// `XDG_CONFIG_HOME` isn't set. Return `Ok(None)` if there is no such
+    /// file.
+    pub fn load() -> Result<Option<Self>, Error> {
+        match path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the configuration file at `path`, if it exists.
+    pub fn load_from(path: &Path) -> Result<Option<Self>, Error> {
+        let toml = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::Io {
+                source: e,
+                path: path.to_owned(),
+            }),
+        };
+
+        let mut file: File = toml::from_str(&toml)
+            .map_err(|e| Error::Toml {
+                source: e,
+                path: path.to_owned(),
+            })?;
+        file.path = path.to_owned();
+
+        Ok(Some(file))
+    }
+
+    /// Get the value of the first of the repository, host and default
+    /// sections that has one, with the name of that section.
+    ///
+    /// `host` is a host name, like `github.com`, and `repo` is a host,
+    /// owner and repository, like `github.com/teddywing/git-suggestion`.
+    pub fn get<'a, F>(
+        &'a self,
+        host: Option<&str>,
+        repo: Option<&str>,
+        value: F,
+    ) -> Option<(&'a str, String)>
+    where F: Fn(&'a Settings) -> &'a Option<String>
+    {
+        let repo_section = repo
+            .and_then(|r| self.repo.get(r).map(|s| (s, section("repo", r))));
+        let host_section = host
+            .and_then(|h| self.host.get(h).map(|s| (s, section("host", h))));
+        let default_section = Some((&self.default, "default".to_owned()));
+
+        repo_section.into_iter()
+            .chain(host_section)
+            .chain(default_section)
+            .find_map(|(settings, name)| {
+                value(settings).as_deref().map(|v| (v, name))
+            })
+    }
+}
+
+/// Get the path of the configuration file, or `None` if neither
+/// `XDG_CONFIG_HOME` nor `HOME` is set.
+pub fn path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| Path::new(&home).join(".config"))
+        })?;
+
+    Some(config_home.join("git-suggestion").join("config.toml"))
+}
+
+/// Format the name of the `kind` section for `key`, as it is written in the
+/// file.
+fn section(kind: &str, key: &str) -> String {
+    format!("{}.\"{}\"", kind, key)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_get_prefers_repo_then_host_then_default() {
+        let file: File = toml::from_str(r#"
+            [default]
+            remote = "origin"
+            format = "quickfix"
+            commit-template = "Apply suggestions"
+
+            [host."github.com"]
+            remote = "upstream"
+            format = "status"
+
+            [repo."github.com/teddywing/git-suggestion"]
+            remote = "teddywing"
+        "#).unwrap();
+
+        let host = Some("github.com");
+        let repo = Some("github.com/teddywing/git-suggestion");
+
+        assert_eq!(
+            file.get(host, repo, |s| &s.remote),
+            Some((
+                "teddywing",
+                r#"repo."github.com/teddywing/git-suggestion""#.to_owned(),
+            )),
+        );
+        assert_eq!(
+            file.get(host, repo, |s| &s.format),
+            Some(("status", r#"host."github.com""#.to_owned())),
+        );
+        assert_eq!(
+            file.get(host, repo, |s| &s.commit_template),
+            Some(("Apply suggestions", "default".to_owned())),
+        );
+        assert_eq!(
+            file.get(None, None, |s| &s.remote),
+            Some(("origin", "default".to_owned())),
+        );
+        assert_eq!(file.get(host, repo, |s| &s.token_command), None);
+    }
+
+    #[test]
+    fn file_rejects_unknown_keys() {
+        let result = toml::from_str::<File>(r#"
+            [default]
+            remotes = "origin"
+        "#);
+
+        assert!(result.is_err());
+    }
+}
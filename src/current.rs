@@ -0,0 +1,194 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use git2::Repository;
+use thiserror::Error;
+
+use github_suggestion::{client, Client};
+
+use crate::config::{self, Config};
+use crate::owner_repo::{self, OwnerRepo};
+
+
+/// Errors finding the pull request for the current branch.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Client(#[from] client::Error),
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    OwnerRepo(#[from] owner_repo::Error),
+
+    #[error("HEAD is not on a branch")]
+    Detached,
+
+    #[error("Branch '{0}' has no upstream")]
+    NoUpstream(String),
+
+    #[error("No open pull request for branch '{0}'")]
+    NoPullRequest(String),
+}
+
+
+/// Looks up open pull requests by their head branch.
+pub trait PullLookup {
+    /// Get the number of the open pull request on `o_r` whose head is
+    /// `head`, given as `owner:branch`.
+    fn pull_for_branch(
+        &self,
+        o_r: &OwnerRepo,
+        head: &str,
+    ) -> Result<Option<u64>, Error>;
+}
+
+/// Looks up pull requests with the GitHub API.
+impl PullLookup for Config {
+    fn pull_for_branch(
+        &self,
+        o_r: &OwnerRepo,
+        head: &str,
+    ) -> Result<Option<u64>, Error> {
+        let client = Client::new(
+            self.require_github_token()?,
+            &o_r.owner,
+            &o_r.repo,
+        )?;
+
+        Ok(client.pull_for_branch(head)?)
+    }
+}
+
+
+/// Get the URL of the open pull request for the current branch of the
+/// repository given by `config`.
+pub fn current_pull_request(config: &Config) -> Result<String, Error> {
+    let repo = config.repository()?;
+
+    current_pull_request_with_repo(config, &repo)
+}
+
+/// Get the URL of the open pull request for the current branch of `repo`.
+///
+/// The pull request's head is the branch's upstream. It is looked up in the
+/// repositories of the candidate remotes, so that pull requests from a fork
+/// to its parent repository are found.
+pub fn current_pull_request_with_repo(
+    config: &Config,
+    repo: &Repository,
+) -> Result<String, Error> {
+    current_pull_request_with_lookup(config, repo, config)
+}
+
+/// Get the URL of the open pull request for the current branch of `repo`,
+/// looking it up with `lookup`.
+pub fn current_pull_request_with_lookup<L: PullLookup + ?Sized>(
+    config: &Config,
+    repo: &Repository,
+    lookup: &L,
+) -> Result<String, Error> {
+    let head = repo.head()?;
+
+    if !head.is_branch() {
+        return Err(Error::Detached);
+    }
+
+    let branch = head.shorthand()
+        .ok_or(Error::Detached)?
+        .to_owned();
+
+    let git_config = repo.config()?;
+    let upstream_config = |key: &str| {
+        match git_config.get_string(&format!("branch.{}.{}", branch, key)) {
+            Err(e) if e.code() == git2::ErrorCode::NotFound =>
+                Err(Error::NoUpstream(branch.clone())),
+            r => Ok(r?),
+        }
+    };
+
+    let remote = upstream_config("remote")?;
+    let merge = upstream_config("merge")?;
+    let upstream_branch = merge.strip_prefix("refs/heads/")
+        .unwrap_or(&merge);
+
+    let head_owner = OwnerRepo::from_remote(repo, &remote)?.owner;
+    let pull_head = format!("{}:{}", head_owner, upstream_branch);
+
+    for o_r in OwnerRepo::from_remotes(repo, &config.remotes)? {
+        if let Some(number) = lookup.pull_for_branch(&o_r, &pull_head)? {
+            return Ok(
+                format!(
+                    "https://{}/{}/{}/pull/{}",
+                    o_r.host,
+                    o_r.owner,
+                    o_r.repo,
+                    number,
+                )
+            );
+        }
+    }
+
+    Err(Error::NoPullRequest(branch))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn current_pull_request_with_repo_requires_upstream() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let tree_id = repo.treebuilder(None).unwrap().write().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let signature = git2::Signature::now(
+            "Araragi Koyomi",
+            "araragi@example.com",
+        ).unwrap();
+        let commit = repo.commit(
+            None,
+            &signature,
+            &signature,
+            "Initial commit",
+            &tree,
+            &[],
+        ).unwrap();
+        let commit = repo.find_commit(commit).unwrap();
+
+        repo.branch("review", &commit, false).unwrap();
+        repo.set_head("refs/heads/review").unwrap();
+
+        let config = Config {
+            current: true,
+            ..Config::default()
+        };
+
+        match current_pull_request_with_repo(&config, &repo) {
+            Err(Error::NoUpstream(branch)) => assert_eq!(branch, "review"),
+            r => panic!("expected no upstream, got {:?}", r),
+        }
+    }
+}
@@ -14,8 +14,9 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 
+
 /// Git diff options.
-static FLAGS: [&'static str; 97] = [
+static FLAGS: [&str; 97] = [
     "--abbrev",
     "--anchored",
     "--binary",
@@ -116,13 +117,26 @@ static FLAGS: [&'static str; 97] = [
 ];
 
 
-/// Parse Git diff options from `args`.
+/// Parse Git diff options from `args`, which start with the program name.
+///
+/// As in Git, `-C <path>` options right after the program name are the
+/// program's own. A `-C` anywhere else is the Git diff option that finds
+/// copies.
 ///
 /// Returns a tuple containing `(args-that-are-not-diff-options, diff-options)`.
 pub fn parse(args: &[String]) -> (Vec<&String>, Vec<&String>) {
     let mut program_args = Vec::new();
     let mut diff_args = Vec::new();
 
+    let mut args = args.iter().peekable();
+
+    program_args.extend(args.next());
+
+    while let Some(arg) = args.next_if(|arg| *arg == "-C") {
+        program_args.push(arg);
+        program_args.extend(args.next());
+    }
+
     'args: for arg in args {
         for flag in FLAGS.iter() {
             if arg.starts_with(flag) {
@@ -146,6 +160,7 @@ mod tests {
     #[test]
     fn parse_extracts_diff_options() {
         let args = vec![
+            "git-sugdiff".to_owned(),
             "--github-token".to_owned(),
             "MY_TOKEN".to_owned(),
             "--diff-filter=A".to_owned(),
@@ -179,6 +194,7 @@ mod tests {
     #[test]
     fn parse_does_not_consume_suggestion_args() {
         let args = vec![
+            "git-sugdiff".to_owned(),
             "--github-token".to_owned(),
             "MY_TOKEN".to_owned(),
             "--word-diff".to_owned(),
@@ -190,10 +206,52 @@ mod tests {
 
         assert_eq!(diff_opts, vec!["--word-diff"]);
         assert_eq!(options, vec![
+            "git-sugdiff",
             "--github-token",
             "MY_TOKEN",
             "459692838",
             "https://github.com/teddywing/git-suggestion/pull/1#discussion_r459691747",
         ]);
     }
+
+    #[test]
+    fn parse_keeps_leading_directory_options() {
+        let args = vec![
+            "git-sugdiff".to_owned(),
+            "-C".to_owned(),
+            "../other-repo".to_owned(),
+            "-C".to_owned(),
+            "nested".to_owned(),
+            "-C50%".to_owned(),
+            "459692838".to_owned(),
+            "-C".to_owned(),
+        ];
+
+        let (options, diff_opts) = parse(&args);
+
+        assert_eq!(diff_opts, vec!["-C50%", "-C"]);
+        assert_eq!(options, vec![
+            "git-sugdiff",
+            "-C",
+            "../other-repo",
+            "-C",
+            "nested",
+            "459692838",
+        ]);
+    }
+
+    #[test]
+    fn parse_treats_later_c_as_a_diff_option() {
+        let args = vec![
+            "git-sugdiff".to_owned(),
+            "--word-diff".to_owned(),
+            "-C".to_owned(),
+            "459692838".to_owned(),
+        ];
+
+        let (options, diff_opts) = parse(&args);
+
+        assert_eq!(diff_opts, vec!["--word-diff", "-C"]);
+        assert_eq!(options, vec!["git-sugdiff", "459692838"]);
+    }
 }
@@ -14,14 +14,95 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 
-use regex;
+use github_suggestion::{client, suggestion, url};
 use thiserror::Error;
 
+use crate::branch;
+use crate::commit;
+use crate::config;
+use crate::current;
+use crate::fixup;
+use crate::format_patch;
+use crate::lsp;
+use crate::owner_repo;
+use crate::push;
+use crate::quickfix;
+use crate::revert;
+use crate::status;
 
+
+/// Errors fetching or operating on suggestions.
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Unable to parse regex")]
     Regex(#[from] regex::Error),
+
+    #[error(transparent)]
+    Branch(#[from] branch::Error),
+
+    #[error(transparent)]
+    Commit(#[from] commit::Error),
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Current(#[from] current::Error),
+
+    #[error(transparent)]
+    Lsp(#[from] lsp::Error),
+
+    #[error(transparent)]
+    OwnerRepo(#[from] owner_repo::Error),
+
+    #[error(transparent)]
+    Url(#[from] url::Error),
+
+    #[error(transparent)]
+    Client(#[from] client::Error),
+
+    #[error(transparent)]
+    Suggestion(#[from] suggestion::Error),
+
+    #[error(transparent)]
+    FormatPatch(#[from] format_patch::Error),
+
+    #[error(transparent)]
+    Fixup(#[from] fixup::Error),
+
+    #[error(transparent)]
+    Push(#[from] push::Error),
+
+    #[error(transparent)]
+    Quickfix(#[from] quickfix::Error),
+
+    #[error(transparent)]
+    Revert(#[from] revert::Error),
+
+    #[error(transparent)]
+    Status(#[from] status::Error),
+
+    #[error("{message}: {source}")]
+    Io {
+        source: std::io::Error,
+        message: String,
+    },
+
+    #[error("{} suggestions failed", .0.len())]
+    Multiple(Vec<Error>),
+}
+
+
+/// Print `error` to standard error. Each error in an `Error::Multiple` is
+/// printed on its own line, followed by a summary.
+pub fn eprint_error(error: &Error) {
+    if let Error::Multiple(errors) = error {
+        for e in errors {
+            eprint_error(e);
+        }
+    }
+
+    crate::gseprintln!(error);
 }
 
 
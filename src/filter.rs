@@ -0,0 +1,203 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use git2::{Pathspec, PathspecFlags};
+use thiserror::Error;
+
+use github_suggestion::Suggestion;
+
+
+/// Errors parsing filter options.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unable to parse date '{0}': use YYYY-MM-DD or an RFC 3339 time")]
+    Date(String),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Criteria that suggestions must meet to be used.
+#[derive(Default)]
+pub struct Filter {
+    /// Only suggestions by this user.
+    pub author: Option<String>,
+
+    /// Only suggestions on files matching these pathspec patterns. A git2
+    /// `Pathspec` can't be shared between threads, so it's compiled when
+    /// needed.
+    pub paths: Vec<String>,
+
+    /// Only suggestions created at or after this time.
+    pub since: Option<DateTime<FixedOffset>>,
+
+    /// Only suggestions from this review.
+    pub review: Option<String>,
+
+    /// Exclude suggestions whose threads were resolved.
+    pub unresolved: bool,
+
+    /// Exclude outdated suggestions.
+    pub not_outdated: bool,
+}
+
+impl Filter {
+    /// Get a filter from the `--author`, `--path`, `--since`, `--review`,
+    /// `--unresolved`, and `--not-outdated` options in `opt_matches`.
+    pub fn from_matches(opt_matches: &getopts::Matches) -> Result<Self, Error> {
+        let paths = opt_matches.opt_strs("path");
+
+        // Check that the patterns are valid.
+        Pathspec::new(&paths)?;
+
+        Ok(Filter {
+            author: opt_matches.opt_str("author"),
+            paths,
+            since: opt_matches.opt_str("since")
+                .map(|date| parse_date(&date))
+                .transpose()?,
+            review: opt_matches.opt_str("review"),
+            unresolved: opt_matches.opt_present("unresolved"),
+            not_outdated: opt_matches.opt_present("not-outdated"),
+        })
+    }
+
+    /// Check whether `suggestion` meets all the criteria.
+    ///
+    /// Suggestions whose resolution isn't known are treated as unresolved.
+    pub fn matches(&self, suggestion: &Suggestion) -> bool {
+        if let Some(author) = &self.author {
+            if !suggestion.author.name.eq_ignore_ascii_case(author) {
+                return false;
+            }
+        }
+
+        if !self.paths.is_empty() {
+            let pathspec = Pathspec::new(&self.paths)
+                .expect("pathspec was checked in `Filter::from_matches()`");
+            let path = Path::new(&suggestion.path);
+
+            if !pathspec.matches_path(path, PathspecFlags::DEFAULT) {
+                return false;
+            }
+        }
+
+        if let Some(since) = &self.since {
+            match DateTime::parse_from_rfc3339(&suggestion.created_at) {
+                Ok(created_at) if created_at >= *since => (),
+                _ => return false,
+            }
+        }
+
+        if let Some(review) = &self.review {
+            if suggestion.review_id.as_ref() != Some(review) {
+                return false;
+            }
+        }
+
+        if self.unresolved && suggestion.resolved == Some(true) {
+            return false;
+        }
+
+        if self.not_outdated && suggestion.outdated {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Parse `date` as either an RFC 3339 time, or a `YYYY-MM-DD` date at
+/// midnight UTC.
+fn parse_date(date: &str) -> Result<DateTime<FixedOffset>, Error> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(time);
+    }
+
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| Error::Date(date.to_owned()))?;
+
+    let midnight = day.and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time");
+
+    Ok(Utc.from_utc_datetime(&midnight).into())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use github_suggestion::{Author, Side};
+
+    fn suggestion(path: &str, created_at: &str) -> Suggestion {
+        Suggestion {
+            id: "1".to_owned(),
+            review_id: Some("10".to_owned()),
+            path: path.to_owned(),
+            commit: String::new(),
+            start_line: 1,
+            end_line: 1,
+            side: Side::Right,
+            message: String::new(),
+            replacement: String::new(),
+            author: Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+            created_at: created_at.to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        }
+    }
+
+    #[test]
+    fn filter_matches_all_criteria() {
+        let filter = Filter {
+            author: Some("Oshino-Shinobu".to_owned()),
+            paths: vec!["src/*.rs".to_owned()],
+            since: Some(parse_date("2020-07-23").unwrap()),
+            review: Some("10".to_owned()),
+            unresolved: true,
+            not_outdated: true,
+        };
+
+        let created_at = "2020-07-23T17:20:29Z";
+
+        assert!(filter.matches(&suggestion("src/lib.rs", created_at)));
+        assert!(!filter.matches(&suggestion("doc/poem.txt", created_at)));
+        assert!(
+            !filter.matches(&suggestion("src/lib.rs", "2020-07-22T23:59:59Z"))
+        );
+
+        let mut resolved = suggestion("src/lib.rs", created_at);
+        resolved.resolved = Some(true);
+        assert!(!filter.matches(&resolved));
+
+        let mut outdated = suggestion("src/lib.rs", created_at);
+        outdated.outdated = true;
+        assert!(!filter.matches(&outdated));
+
+        let mut other_review = suggestion("src/lib.rs", created_at);
+        other_review.review_id = None;
+        assert!(!filter.matches(&other_review));
+    }
+}
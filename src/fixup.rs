@@ -0,0 +1,332 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::path::Path;
+
+use git2::{BlameOptions, Commit, Oid, Repository};
+use thiserror::Error;
+
+use github_suggestion::{suggestion, Suggestion};
+
+
+/// Errors creating a fixup commit for a suggestion.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Suggestion(#[from] suggestion::Error),
+
+    #[error("HEAD is not on a branch")]
+    Detached,
+
+    #[error("Branch '{0}' has no upstream")]
+    NoUpstream(String),
+
+    #[error("Lines {start}-{end} of {path} were not changed on this branch")]
+    NotOnBranch {
+        path: String,
+        start: usize,
+        end: usize,
+    },
+
+    #[error("Lines {start}-{end} of {path} were changed by several commits: {commits}")]
+    MultipleCommits {
+        path: String,
+        start: usize,
+        end: usize,
+        commits: String,
+    },
+
+    #[error("The index has staged changes")]
+    StagedChanges,
+}
+
+
+/// Apply `suggestion` and commit it as a fixup of the commit on the current
+/// branch that introduced the suggestion's lines, in the repository in the
+/// current environment.
+///
+/// Returns the ID of the fixup commit.
+pub fn fixup(suggestion: &Suggestion) -> Result<Oid, Error> {
+    let repo = Repository::open_from_env()?;
+
+    fixup_with_repo(suggestion, &repo)
+}
+
+/// Apply `suggestion` and commit it in `repo` as a fixup of the commit on the
+/// current branch that introduced the suggestion's lines.
+///
+/// The branch's own commits are the ones that aren't on its upstream. The
+/// suggestion is applied to both the index and the working tree, so the index
+/// must not have other staged changes.
+pub fn fixup_with_repo(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<Oid, Error> {
+    let head = repo.head()?;
+    let head_commit = head.peel_to_commit()?;
+    let base = upstream_merge_base(repo, head, head_commit.id())?;
+    let target = target_commit(repo, suggestion, base)?;
+
+    let mut index = repo.index()?;
+    let staged = repo.diff_tree_to_index(
+        Some(&head_commit.tree()?),
+        Some(&index),
+        None,
+    )?;
+
+    if staged.deltas().len() > 0 {
+        return Err(Error::StagedChanges);
+    }
+
+    suggestion.apply_at(repo, git2::ApplyLocation::Both)?;
+
+    index.read(true)?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let signature = repo.signature()?;
+    let message = format!(
+        "fixup! {}",
+        String::from_utf8_lossy(target.summary_bytes().unwrap_or_default()),
+    );
+
+    Ok(
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &[&head_commit],
+        )?
+    )
+}
+
+/// Get the merge base of `head` and its upstream branch.
+fn upstream_merge_base(
+    repo: &Repository,
+    head: git2::Reference<'_>,
+    head_id: Oid,
+) -> Result<Oid, Error> {
+    if !head.is_branch() {
+        return Err(Error::Detached);
+    }
+
+    let name = head.shorthand()
+        .unwrap_or("HEAD")
+        .to_owned();
+
+    let upstream = match git2::Branch::wrap(head).upstream() {
+        Ok(u) => u,
+        Err(e) if e.code() == git2::ErrorCode::NotFound =>
+            return Err(Error::NoUpstream(name)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let upstream_id = upstream.get().peel_to_commit()?.id();
+
+    Ok(repo.merge_base(head_id, upstream_id)?)
+}
+
+/// Find the commit after `base` that introduced all of the suggestion's
+/// lines, by blaming the file at the suggestion's commit.
+fn target_commit<'r>(
+    repo: &'r Repository,
+    suggestion: &Suggestion,
+    base: Oid,
+) -> Result<Commit<'r>, Error> {
+    let mut opts = BlameOptions::new();
+    opts.newest_commit(suggestion.commit.parse()?)
+        .oldest_commit(base);
+
+    let blame = repo.blame_file(Path::new(&suggestion.path), Some(&mut opts))?;
+
+    let not_on_branch = || Error::NotOnBranch {
+        path: suggestion.path.clone(),
+        start: suggestion.start_line,
+        end: suggestion.end_line,
+    };
+
+    let mut commits = Vec::new();
+
+    for line in suggestion.start_line..=suggestion.end_line {
+        let hunk = blame.get_line(line)
+            .ok_or_else(not_on_branch)?;
+        let id = hunk.final_commit_id();
+
+        if hunk.is_boundary() || id == base {
+            return Err(not_on_branch());
+        }
+
+        if !commits.contains(&id) {
+            commits.push(id);
+        }
+    }
+
+    match commits.as_slice() {
+        [id] => Ok(repo.find_commit(*id)?),
+        _ => Err(Error::MultipleCommits {
+            path: suggestion.path.clone(),
+            start: suggestion.start_line,
+            end: suggestion.end_line,
+            commits: commits.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use github_suggestion::{Author, Side};
+    use tempfile::tempdir;
+
+    /// Write `content` to `path` in the working tree of `repo`, and commit it
+    /// on HEAD with `message`.
+    fn commit(repo: &Repository, path: &str, content: &str, message: &str) -> Oid {
+        fs::write(repo.workdir().unwrap().join(path), content).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let signature = repo.signature().unwrap();
+        let parents = match repo.head() {
+            Ok(head) => vec![head.peel_to_commit().unwrap()],
+            Err(_) => Vec::new(),
+        };
+        let parents: Vec<_> = parents.iter().collect();
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        ).unwrap()
+    }
+
+    fn suggestion(commit: Oid, start_line: usize, end_line: usize) -> Suggestion {
+        Suggestion {
+            id: "1".to_owned(),
+            review_id: None,
+            path: "Jabberwocky.txt".to_owned(),
+            commit: commit.to_string(),
+            start_line,
+            end_line,
+            side: Side::Right,
+            message: String::new(),
+            replacement: "He took his vorpal sword in hand:\n".to_owned(),
+            author: Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        }
+    }
+
+    #[test]
+    fn fixup_with_repo_commits_fixup_of_commit_that_changed_lines() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Oshino Shinobu").unwrap();
+        config.set_str("user.email", "oshino.shinobu@example.com").unwrap();
+
+        let path = "Jabberwocky.txt";
+        let base = commit(
+            &repo,
+            path,
+            "Beware the Jubjub bird, and shun\n\
+                He took his blade in hand:\n\
+                So rested he by the Tumtum tree,\n",
+            "Add poem",
+        );
+
+        let mut branch = repo.branch(
+            "vorpal",
+            &repo.find_commit(base).unwrap(),
+            false,
+        ).unwrap();
+        branch.set_upstream(Some("master")).unwrap();
+        repo.set_head("refs/heads/vorpal").unwrap();
+
+        commit(
+            &repo,
+            path,
+            "Beware the Jubjub bird, and shun\n\
+                He took his vorpal blade in hand:\n\
+                So rested he by the Tumtum tree,\n",
+            "Sharpen the blade",
+        );
+        let head = commit(
+            &repo,
+            path,
+            "Beware the Jubjub bird, and shun\n\
+                He took his vorpal blade in hand:\n\
+                So rested he by the Tumtum tree;\n\
+                And stood awhile in thought.\n",
+            "Add a line",
+        );
+
+        match fixup_with_repo(&suggestion(head, 1, 1), &repo) {
+            Err(Error::NotOnBranch { .. }) => (),
+            r => panic!("expected lines not on branch, got {:?}", r),
+        }
+
+        match fixup_with_repo(&suggestion(head, 2, 3), &repo) {
+            Err(Error::MultipleCommits { .. }) => (),
+            r => panic!("expected several commits, got {:?}", r),
+        }
+
+        let fixup = fixup_with_repo(&suggestion(head, 2, 2), &repo).unwrap();
+        let fixup = repo.find_commit(fixup).unwrap();
+
+        assert_eq!(fixup.message(), Some("fixup! Sharpen the blade"));
+        assert_eq!(fixup.parent_id(0).unwrap(), head);
+
+        let expected = "Beware the Jubjub bird, and shun\n\
+            He took his vorpal sword in hand:\n\
+            So rested he by the Tumtum tree;\n\
+            And stood awhile in thought.\n";
+
+        let blob = fixup.tree().unwrap()
+            .get_path(Path::new(path)).unwrap()
+            .to_object(&repo).unwrap()
+            .peel_to_blob().unwrap();
+
+        assert_eq!(std::str::from_utf8(blob.content()).unwrap(), expected);
+        assert_eq!(
+            fs::read_to_string(git_root.path().join(path)).unwrap(),
+            expected,
+        );
+    }
+}
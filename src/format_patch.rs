@@ -0,0 +1,256 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+use chrono::DateTime;
+use git2::Repository;
+use thiserror::Error;
+
+use github_suggestion::{suggestion, Suggestion};
+
+use crate::VERSION;
+
+
+/// Errors formatting a suggestion as a patch email.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Suggestion(#[from] suggestion::Error),
+
+    #[error("Unable to parse comment date: {0}")]
+    Date(#[from] chrono::ParseError),
+}
+
+
+/// Format `suggestion` as an mbox patch email that can be applied with
+/// `git am`.
+///
+/// The author and date of the email are taken from the suggestion comment.
+/// The first line of the comment text becomes the subject, and any following
+/// lines become the body of the commit message. Authors without an email
+/// address get a no-reply address for their login.
+///
+/// The diff is included as is, so the patch is in the encoding of the
+/// suggestion's file. A UTF-8 charset is only declared when the diff is valid
+/// UTF-8.
+pub fn format_patch(suggestion: &Suggestion) -> Result<Vec<u8>, Error> {
+    let diff = suggestion.diff()?;
+
+    mbox(suggestion, &diff)
+}
+
+/// Format `suggestion` as an mbox patch email, using `repo` to generate the
+/// diff.
+pub fn format_patch_with_repo(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<Vec<u8>, Error> {
+    let diff = suggestion.diff_with_repo(repo)?;
+
+    mbox(suggestion, &diff)
+}
+
+/// Build an mbox email from the metadata in `suggestion` and `diff`.
+fn mbox(suggestion: &Suggestion, diff: &[u8]) -> Result<Vec<u8>, Error> {
+    let date = DateTime::parse_from_rfc3339(&suggestion.created_at)?;
+
+    let mut lines = suggestion.message.lines();
+    let subject = match lines.next() {
+        Some(s) if !s.trim().is_empty() => s.trim().to_owned(),
+        _ => format!("Apply suggestion to {}", suggestion.path),
+    };
+    let body = lines.collect::<Vec<_>>().join("\n");
+    let body = body.trim();
+
+    let author = &suggestion.author;
+
+    let mut patch = format!(
+        "From {commit} Mon Sep 17 00:00:00 2001\n\
+        From: {name} <{email}>\n\
+        Date: {date}\n\
+        Subject: [PATCH] {subject}\n",
+        commit = suggestion.commit,
+        name = author.name,
+        email = suggestion.author_email(),
+        date = date.to_rfc2822(),
+        subject = subject,
+    );
+
+    // The diff is in the encoding of the file, so it can't be labelled as
+    // UTF-8 if the file uses another encoding.
+    if std::str::from_utf8(diff).is_ok() {
+        patch.push_str(
+            "MIME-Version: 1.0\n\
+            Content-Type: text/plain; charset=UTF-8\n\
+            Content-Transfer-Encoding: 8bit\n",
+        );
+    }
+
+    patch.push('\n');
+
+    for line in body.lines() {
+        patch.push_str(line);
+        patch.push('\n');
+    }
+
+    patch.push_str("---\n");
+
+    let mut patch = patch.into_bytes();
+    patch.extend_from_slice(diff);
+    patch.extend_from_slice(
+        format!("-- \ngit-suggestion {}\n\n", VERSION).as_bytes(),
+    );
+
+    Ok(patch)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    use github_suggestion::{Author, Side};
+    use tempfile::tempdir;
+
+    fn suggestion(commit: &str, message: &str) -> Suggestion {
+        Suggestion {
+            id: "459691747".to_owned(),
+            review_id: None,
+            path: "Jabberwocky.txt".to_owned(),
+            commit: commit.to_owned(),
+            start_line: 2,
+            end_line: 2,
+            side: Side::Right,
+            message: message.to_owned(),
+            replacement: "Did gyre and gimble in the wabe!\n".to_owned(),
+            author: Author {
+                name: "oshino".to_owned(),
+                email: "oshino@users.noreply.github.com".to_owned(),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        }
+    }
+
+    #[test]
+    fn format_patch_with_repo_applies_with_git_am() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Araragi Koyomi").unwrap();
+        config.set_str("user.email", "araragi@example.com").unwrap();
+
+        fs::write(
+            git_root.path().join("Jabberwocky.txt"),
+            "’Twas brillig, and the slithy toves\n\
+                Did gyre and gimble in the wabe:\n\
+                All mimsy were the borogoves,\n",
+        ).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("Jabberwocky.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let signature = repo.signature().unwrap();
+        let base = repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Add poem",
+            &tree,
+            &[],
+        ).unwrap();
+
+        let suggestion = suggestion(
+            &base.to_string(),
+            "Exclaim the gimbling\n\
+                \n\
+                From the second stanza.\n\
+                >From the third.",
+        );
+
+        let patch = format_patch_with_repo(&suggestion, &repo).unwrap();
+        let patch_path = git_root.path().join("suggestion.patch");
+        fs::write(&patch_path, &patch).unwrap();
+
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(git_root.path())
+            .args(["am", "-3"])
+            .arg(&patch_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let commit = repo.head().unwrap().peel_to_commit().unwrap();
+
+        assert_eq!(commit.author().name(), Some("oshino"));
+        assert_eq!(
+            commit.author().email(),
+            Some("oshino@users.noreply.github.com"),
+        );
+        assert_eq!(
+            commit.message(),
+            Some(
+                "Exclaim the gimbling\n\
+                    \n\
+                    From the second stanza.\n\
+                    >From the third.\n",
+            ),
+        );
+        assert_eq!(
+            fs::read_to_string(git_root.path().join("Jabberwocky.txt"))
+                .unwrap(),
+            "’Twas brillig, and the slithy toves\n\
+                Did gyre and gimble in the wabe!\n\
+                All mimsy were the borogoves,\n",
+        );
+    }
+
+    #[test]
+    fn mbox_credits_authors_without_email_with_no_reply_address() {
+        let mut suggestion = suggestion("89840a2", "Exclaim the gimbling");
+        suggestion.author.email = String::new();
+
+        let patch = mbox(&suggestion, b"").unwrap();
+        assert!(
+            String::from_utf8_lossy(&patch).contains(
+                "From: oshino <oshino@users.noreply.github.com>\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn mbox_only_declares_utf8_for_utf8_diffs() {
+        let suggestion = suggestion("89840a2", "Exclaim the gimbling");
+
+        let patch = mbox(&suggestion, "+’Twas\n".as_bytes()).unwrap();
+        assert!(
+            String::from_utf8_lossy(&patch)
+                .contains("Content-Type: text/plain; charset=UTF-8\n"),
+        );
+
+        let patch = mbox(&suggestion, b"+\x92Twas\n").unwrap();
+        assert!(!String::from_utf8_lossy(&patch).contains("charset"));
+    }
+}
@@ -16,16 +16,33 @@
 
 #![warn(rust_2018_idioms)]
 
+pub mod branch;
+pub mod commit;
 pub mod config;
+pub mod config_file;
+pub mod current;
 pub mod diff_options;
 pub mod error;
+pub mod filter;
+pub mod fixup;
+pub mod format_patch;
+pub mod lsp;
+pub mod push;
+pub mod quickfix;
+pub mod revert;
+pub mod status;
 
 mod arg;
 mod owner_repo;
 mod suggestion;
 
 
-pub use suggestion::for_suggestion;
+pub use suggestion::{
+    for_suggestion,
+    pull_request_suggestions,
+    pull_suggestions,
+    suggestions,
+};
 
 
-const VERSION: &'static str = "0.2.1";
+const VERSION: &str = "0.2.1";
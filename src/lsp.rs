@@ -0,0 +1,954 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::panic;
+use std::thread;
+
+use crossbeam_channel::bounded;
+use git2::Repository;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::{
+    CodeAction,
+    CodeActionKind,
+    CodeActionOrCommand,
+    CodeActionParams,
+    CodeActionProviderCapability,
+    Diagnostic,
+    DiagnosticSeverity,
+    DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams,
+    LogMessageParams,
+    MessageType,
+    Position,
+    PublishDiagnosticsParams,
+    Range,
+    ServerCapabilities,
+    ShowMessageParams,
+    TextDocumentSyncCapability,
+    TextDocumentSyncKind,
+    TextEdit,
+    Url,
+    WorkspaceEdit,
+};
+use lsp_types::notification::{
+    self as notification,
+    Notification as _,
+};
+use lsp_types::request::{self as request, Request as _};
+use thiserror::Error;
+
+use github_suggestion::Suggestion;
+use github_suggestion::suggestion::Location;
+
+use crate::config::Config;
+use crate::current::{current_pull_request_with_lookup, PullLookup};
+use crate::error;
+use crate::quickfix::summary;
+use crate::suggestion::pull_request_suggestions;
+
+
+/// The name that diagnostics are published under.
+const DIAGNOSTIC_SOURCE: &str = "git-suggestion";
+
+/// JSON-RPC error code for unknown methods.
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// JSON-RPC error code for parameters that don't match the method.
+const INVALID_PARAMS: i32 = -32602;
+
+/// Errors running the language server.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Protocol(#[from] lsp_server::ProtocolError),
+
+    #[error("Unable to serialize message: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unable to send message: {0}")]
+    Send(String),
+}
+
+
+/// Where the language server gets its suggestions.
+pub trait Source {
+    /// Get the suggestions to serve.
+    fn suggestions(&self) -> Result<Vec<Suggestion>, error::Error>;
+}
+
+/// The forge API calls that `PullRequestSource` makes.
+pub trait Api: PullLookup {
+    /// Get the suggestions on the pull request at `pull_url`.
+    fn pull_request_suggestions(
+        &self,
+        pull_url: &str,
+    ) -> Result<Vec<Suggestion>, error::Error>;
+}
+
+/// Gets suggestions with the GitHub API.
+impl Api for Config {
+    fn pull_request_suggestions(
+        &self,
+        pull_url: &str,
+    ) -> Result<Vec<Suggestion>, error::Error> {
+        pull_request_suggestions(self, pull_url)
+    }
+}
+
+/// Gets the suggestions on the open pull request for the current branch.
+pub struct PullRequestSource<'a> {
+    config: &'a Config,
+    repo: &'a Repository,
+    api: &'a dyn Api,
+}
+
+impl<'a> PullRequestSource<'a> {
+    /// Get the suggestions for the current branch of `repo` from the forge
+    /// API.
+    pub fn new(config: &'a Config, repo: &'a Repository) -> Self {
+        Self::with_api(config, repo, config)
+    }
+
+    /// Get the suggestions for the current branch of `repo` with `api`.
+    pub fn with_api(
+        config: &'a Config,
+        repo: &'a Repository,
+        api: &'a dyn Api,
+    ) -> Self {
+        PullRequestSource { config, repo, api }
+    }
+}
+
+impl Source for PullRequestSource<'_> {
+    fn suggestions(&self) -> Result<Vec<Suggestion>, error::Error> {
+        let pull_url = current_pull_request_with_lookup(
+            self.config,
+            self.repo,
+            self.api,
+        )?;
+
+        self.api.pull_request_suggestions(&pull_url)
+    }
+}
+
+
+/// The threads that read and write the messages of a connection made with
+/// `connect`.
+pub struct IoThreads {
+    reader: thread::JoinHandle<io::Result<()>>,
+    writer: thread::JoinHandle<io::Result<()>>,
+}
+
+impl IoThreads {
+    /// Wait for the threads to finish. The writer finishes when the
+    /// connection is dropped.
+    pub fn join(self) -> io::Result<()> {
+        let reader = self.reader.join()
+            .unwrap_or_else(|e| panic::resume_unwind(e));
+        let writer = self.writer.join()
+            .unwrap_or_else(|e| panic::resume_unwind(e));
+
+        reader.and(writer)
+    }
+}
+
+/// Make a connection that reads messages framed with `Content-Length`
+/// headers from `reader`, and writes them to `writer`, such as standard
+/// input and output.
+///
+/// Reading stops at the end of the input or after the exit notification.
+pub fn connect<R, W>(reader: R, writer: W) -> (Connection, IoThreads)
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let (writer_sender, writer_receiver) = bounded::<Message>(0);
+    let writer = thread::spawn(move || {
+        let mut writer = writer;
+
+        writer_receiver.into_iter()
+            .try_for_each(|message| message.write(&mut writer))
+    });
+
+    let (reader_sender, reader_receiver) = bounded::<Message>(0);
+    let reader = thread::spawn(move || {
+        let mut reader = reader;
+
+        while let Some(message) = Message::read(&mut reader)? {
+            let is_exit = match &message {
+                Message::Notification(n) =>
+                    n.method == notification::Exit::METHOD,
+                _ => false,
+            };
+
+            reader_sender.send(message)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+            if is_exit {
+                break;
+            }
+        }
+
+        Ok(())
+    });
+
+    (
+        Connection { sender: writer_sender, receiver: reader_receiver },
+        IoThreads { reader, writer },
+    )
+}
+
+/// Serve the suggestions from `source` for files in `repo` over
+/// `connection`, until the client shuts the server down.
+///
+/// Suggestions are published as diagnostics on open documents, on the lines
+/// where they are in the document. Each one has a code action that applies
+/// it. If the suggestions can't be fetched, the error is shown to the user
+/// and no suggestions are served.
+///
+/// Requests with invalid parameters get an error response, and notifications
+/// with invalid parameters are logged and skipped.
+pub fn run(
+    connection: &Connection,
+    repo: &Repository,
+    source: &dyn Source,
+) -> Result<(), Error> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(
+            TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL),
+        ),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    };
+
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let suggestions = match source.suggestions() {
+        Ok(s) => s,
+        Err(e) => {
+            send(
+                connection,
+                Notification::new(
+                    notification::ShowMessage::METHOD.to_owned(),
+                    ShowMessageParams {
+                        typ: MessageType::ERROR,
+                        message: format!("git-suggestion: {}", e),
+                    },
+                ),
+            )?;
+
+            Vec::new()
+        },
+    };
+
+    let mut server = Server {
+        connection,
+        repo,
+        suggestions,
+        documents: HashMap::new(),
+    };
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+
+                server.handle_request(req)?;
+            },
+            Message::Notification(not) => server.handle_notification(not)?,
+            Message::Response(_) => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Language server state.
+struct Server<'a> {
+    connection: &'a Connection,
+    repo: &'a Repository,
+    suggestions: Vec<Suggestion>,
+
+    /// The text of the open documents.
+    documents: HashMap<Url, String>,
+}
+
+impl Server<'_> {
+    fn handle_request(&self, req: Request) -> Result<(), Error> {
+        let response = if req.method == request::CodeActionRequest::METHOD {
+            let id = req.id.clone();
+
+            match req.extract::<CodeActionParams>(
+                request::CodeActionRequest::METHOD,
+            ) {
+                Ok((id, params)) =>
+                    Response::new_ok(id, self.code_actions(&params)),
+                Err(e) => Response::new_err(id, INVALID_PARAMS, e.to_string()),
+            }
+        } else {
+            Response::new_err(
+                req.id,
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{}'", req.method),
+            )
+        };
+
+        send(self.connection, response)
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<(), Error> {
+        let method = not.method.clone();
+
+        match self.update_document(not) {
+            Ok(Some(uri)) => self.publish_diagnostics(uri),
+            Ok(None) => Ok(()),
+            Err(e) => send(
+                self.connection,
+                Notification::new(
+                    notification::LogMessage::METHOD.to_owned(),
+                    LogMessageParams {
+                        typ: MessageType::WARNING,
+                        message: format!(
+                            "git-suggestion: Invalid params for '{}': {}",
+                            method,
+                            e,
+                        ),
+                    },
+                ),
+            ),
+        }
+    }
+
+    /// Update the open documents from a text document notification, and get
+    /// the URI of the document that it's about.
+    fn update_document(
+        &mut self,
+        not: Notification,
+    ) -> Result<Option<Url>, serde_json::Error> {
+        let uri = match not.method.as_str() {
+            notification::DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+
+                self.documents.insert(
+                    params.text_document.uri.clone(),
+                    params.text_document.text,
+                );
+
+                params.text_document.uri
+            },
+            notification::DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+
+                // Changes are always full documents.
+                let change = params.content_changes.into_iter().last();
+
+                if let Some(change) = change {
+                    self.documents.insert(
+                        params.text_document.uri.clone(),
+                        change.text,
+                    );
+                }
+
+                params.text_document.uri
+            },
+            notification::DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+
+                self.documents.remove(&params.text_document.uri);
+
+                params.text_document.uri
+            },
+            _ => return Ok(None),
+        };
+
+        Ok(Some(uri))
+    }
+
+    /// Publish a diagnostic for each suggestion in the document at `uri`.
+    fn publish_diagnostics(&self, uri: Url) -> Result<(), Error> {
+        let diagnostics = self.located(&uri)
+            .into_iter()
+            .map(|(suggestion, range)| Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: None,
+                code_description: None,
+                source: Some(DIAGNOSTIC_SOURCE.to_owned()),
+                message: format!(
+                    "{}: {}",
+                    suggestion.author.name,
+                    summary(suggestion),
+                ),
+                related_information: None,
+                tags: None,
+                data: Some(serde_json::Value::String(suggestion.id.clone())),
+            })
+            .collect();
+
+        send(
+            self.connection,
+            Notification::new(
+                notification::PublishDiagnostics::METHOD.to_owned(),
+                PublishDiagnosticsParams {
+                    uri,
+                    diagnostics,
+                    version: None,
+                },
+            ),
+        )
+    }
+
+    /// Get a code action for each suggestion in the requested range that
+    /// replaces the document with the suggestion applied.
+    fn code_actions(
+        &self,
+        params: &CodeActionParams,
+    ) -> Vec<CodeActionOrCommand> {
+        let uri = &params.text_document.uri;
+        let text = match self.documents.get(uri) {
+            Some(t) => t,
+            None => return Vec::new(),
+        };
+
+        self.located(uri)
+            .into_iter()
+            .filter(|(_, range)| overlaps(range, &params.range))
+            .filter_map(|(suggestion, range)| {
+                let start_line = range.start.line as usize + 1;
+                let new_content = suggestion.apply_to_text(text, start_line)
+                    .ok()?;
+
+                // Only the suggestion's lines change, so the new text for
+                // them is what's left between the unchanged lines around
+                // them.
+                let start = line_offset(text, range.start.line);
+                let end = line_offset(text, range.end.line);
+                let new_text = new_content
+                    .get(start..new_content.len() - (text.len() - end))?
+                    .to_owned();
+
+                let end_position = if end == text.len() {
+                    end_of(text)
+                } else {
+                    range.end
+                };
+
+                let edit = TextEdit {
+                    range: Range::new(range.start, end_position),
+                    new_text,
+                };
+
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!(
+                        "Apply suggestion from {}",
+                        suggestion.author.name,
+                    ),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(
+                            vec![(uri.clone(), vec![edit])]
+                                .into_iter()
+                                .collect(),
+                        ),
+                        ..WorkspaceEdit::default()
+                    }),
+                    ..CodeAction::default()
+                }))
+            })
+            .collect()
+    }
+
+    /// Find the suggestions that can be applied to the open document at
+    /// `uri`, with the range of lines they replace.
+    fn located(&self, uri: &Url) -> Vec<(&Suggestion, Range)> {
+        let text = match self.documents.get(uri) {
+            Some(t) => t,
+            None => return Vec::new(),
+        };
+
+        let path = match self.repo_path(uri) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+
+        self.suggestions.iter()
+            .filter(|s| s.path == path)
+            .filter_map(|s| {
+                match s.locate(self.repo, text.as_bytes()) {
+                    Ok(Location::Original(line)) => {
+                        let start = line as u32 - 1;
+                        let end = start
+                            + (s.end_line - s.start_line) as u32
+                            + 1;
+
+                        Some((
+                            s,
+                            Range::new(
+                                Position::new(start, 0),
+                                Position::new(end, 0),
+                            ),
+                        ))
+                    },
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Get the path of the file at `uri` relative to the working tree, with
+    /// `/` separators.
+    fn repo_path(&self, uri: &Url) -> Option<String> {
+        let path = uri.to_file_path().ok()?;
+        let workdir = self.repo.workdir()?;
+
+        // The paths may be canonicalised differently, for example through
+        // a symbolic link.
+        let relative = match path.strip_prefix(workdir) {
+            Ok(r) => r.to_path_buf(),
+            Err(_) => path.canonicalize().ok()?
+                .strip_prefix(workdir.canonicalize().ok()?)
+                .ok()?
+                .to_path_buf(),
+        };
+
+        Some(
+            relative.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        )
+    }
+}
+
+/// Send `message` to the client.
+fn send<M: Into<Message>>(
+    connection: &Connection,
+    message: M,
+) -> Result<(), Error> {
+    connection.sender
+        .send(message.into())
+        .map_err(|e| Error::Send(e.to_string()))
+}
+
+/// Check whether the line ranges `a` and `b` overlap.
+fn overlaps(a: &Range, b: &Range) -> bool {
+    a.start.line <= b.end.line
+        && b.start.line < a.end.line.max(a.start.line + 1)
+}
+
+/// Get the byte offset of the start of line `line` in `text`, or the length
+/// of `text` if it has fewer lines.
+fn line_offset(text: &str, line: u32) -> usize {
+    if line == 0 {
+        return 0;
+    }
+
+    text.match_indices('\n')
+        .nth(line as usize - 1)
+        .map_or(text.len(), |(i, _)| i + 1)
+}
+
+/// Get the position of the end of `text`, in UTF-16 code units.
+fn end_of(text: &str) -> Position {
+    let line = text.matches('\n').count() as u32;
+    let last_line = text.rsplit('\n').next().unwrap_or_default();
+
+    Position::new(line, last_line.encode_utf16().count() as u32)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use github_suggestion::{Author, Side};
+    use lsp_server::RequestId;
+    use lsp_types::{
+        CodeActionContext,
+        InitializeParams,
+        InitializedParams,
+        TextDocumentIdentifier,
+        TextDocumentItem,
+    };
+    use tempfile::tempdir;
+
+    use crate::current;
+    use crate::owner_repo::OwnerRepo;
+
+    const PULL_URL: &str = "https://github.com/teddywing/git-suggestion/pull/1";
+
+    /// Serves one pull request, whose head is the `jabberwocky` branch.
+    struct FakeApi(Vec<Suggestion>);
+
+    impl PullLookup for FakeApi {
+        fn pull_for_branch(
+            &self,
+            o_r: &OwnerRepo,
+            head: &str,
+        ) -> Result<Option<u64>, current::Error> {
+            let pull = (o_r.owner.as_str(), o_r.repo.as_str(), head);
+
+            match pull {
+                ("teddywing", "git-suggestion", "teddywing:jabberwocky") =>
+                    Ok(Some(1)),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    impl Api for FakeApi {
+        fn pull_request_suggestions(
+            &self,
+            pull_url: &str,
+        ) -> Result<Vec<Suggestion>, error::Error> {
+            assert_eq!(pull_url, PULL_URL);
+
+            Ok(self.0.clone())
+        }
+    }
+
+    /// Has no suggestions.
+    struct NoSuggestions;
+
+    impl Source for NoSuggestions {
+        fn suggestions(&self) -> Result<Vec<Suggestion>, error::Error> {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Collects what the server writes, so it can be read after the writer
+    /// thread finishes.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run the server over a framed input and output, with the client
+    /// sending `messages`, and get the messages that the server wrote.
+    fn serve(
+        repo: &Repository,
+        source: &dyn Source,
+        messages: Vec<Message>,
+    ) -> Vec<Message> {
+        let output = Output::default();
+        let (connection, io_threads) = connect(
+            io::Cursor::new(script(messages)),
+            output.clone(),
+        );
+
+        run(&connection, repo, source).unwrap();
+        drop(connection);
+        io_threads.join().unwrap();
+
+        let output = output.0.lock().unwrap();
+
+        read_messages(&output)
+    }
+
+    /// The messages a client sends to start a session.
+    fn initialize() -> Vec<Message> {
+        vec![
+            Request::new(
+                RequestId::from(1),
+                request::Initialize::METHOD.to_owned(),
+                InitializeParams::default(),
+            ).into(),
+            Notification::new(
+                notification::Initialized::METHOD.to_owned(),
+                InitializedParams {},
+            ).into(),
+        ]
+    }
+
+    /// The messages a client sends to end a session, with the shutdown
+    /// request numbered `id`.
+    fn shut_down(id: i32) -> Vec<Message> {
+        vec![
+            Request::new(
+                RequestId::from(id),
+                request::Shutdown::METHOD.to_owned(),
+                (),
+            ).into(),
+            Notification::new(
+                notification::Exit::METHOD.to_owned(),
+                (),
+            ).into(),
+        ]
+    }
+
+    /// Frame `messages` as a client writes them to the server's standard
+    /// input.
+    fn script(messages: Vec<Message>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for message in messages {
+            message.write(&mut bytes).unwrap();
+        }
+
+        bytes
+    }
+
+    /// Read the framed messages in `bytes`.
+    fn read_messages(mut bytes: &[u8]) -> Vec<Message> {
+        let mut messages = Vec::new();
+
+        while let Some(message) = Message::read(&mut bytes).unwrap() {
+            messages.push(message);
+        }
+
+        messages
+    }
+
+    const POEM: &str = "’Twas brillig, and the slithy toves\n\
+        Did gyre and gimble in the wabe:\n\
+        All mimsy were the borogoves,\n\
+        And the mome raths outgrabe.\n";
+
+    #[test]
+    fn run_serves_suggestions_as_diagnostics_and_code_actions() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let path = "Jabberwocky.txt";
+        let blob = repo.blob(POEM.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert(path, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let signature = git2::Signature::now(
+            "Araragi Koyomi",
+            "araragi@example.com",
+        ).unwrap();
+        let commit = repo.commit(
+            None,
+            &signature,
+            &signature,
+            "Add poem",
+            &tree,
+            &[],
+        ).unwrap();
+
+        // The local branch tracks a differently named upstream branch, which
+        // is the head of the pull request.
+        repo.branch("review", &repo.find_commit(commit).unwrap(), false)
+            .unwrap();
+        repo.set_head("refs/heads/review").unwrap();
+        repo.remote(
+            "origin",
+            "https://github.com/teddywing/git-suggestion.git",
+        ).unwrap();
+
+        let mut git_config = repo.config().unwrap();
+        git_config.set_str("branch.review.remote", "origin").unwrap();
+        git_config.set_str("branch.review.merge", "refs/heads/jabberwocky")
+            .unwrap();
+
+        let suggestion = Suggestion {
+            id: "1".to_owned(),
+            review_id: None,
+            path: path.to_owned(),
+            commit: commit.to_string(),
+            start_line: 2,
+            end_line: 2,
+            side: Side::Right,
+            message: String::new(),
+            replacement: "Did gyre and gimble in the wabe;\n".to_owned(),
+            author: Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        };
+
+        // The document has moved on since the suggestion was made.
+        let text = format!("Jabberwocky\n\n{}", POEM);
+        let uri = Url::from_file_path(repo.workdir().unwrap().join(path))
+            .unwrap();
+
+        let mut input = initialize();
+        input.extend(vec![
+            Notification::new(
+                notification::DidOpenTextDocument::METHOD.to_owned(),
+                DidOpenTextDocumentParams {
+                    text_document: TextDocumentItem::new(
+                        uri.clone(),
+                        "text".to_owned(),
+                        1,
+                        text,
+                    ),
+                },
+            ).into(),
+            Request::new(
+                RequestId::from(2),
+                request::CodeActionRequest::METHOD.to_owned(),
+                CodeActionParams {
+                    text_document: TextDocumentIdentifier::new(uri.clone()),
+                    range: Range::new(
+                        Position::new(3, 0),
+                        Position::new(3, 0),
+                    ),
+                    context: CodeActionContext::default(),
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                },
+            ).into(),
+        ]);
+        input.extend(shut_down(3));
+
+        let config = Config::default();
+        let api = FakeApi(vec![suggestion]);
+
+        let messages = serve(
+            &repo,
+            &PullRequestSource::with_api(&config, &repo, &api),
+            input,
+        );
+
+        let diagnostics = messages.iter()
+            .find_map(|m| match m {
+                Message::Notification(n)
+                    if n.method == notification::PublishDiagnostics::METHOD =>
+                    Some(
+                        serde_json::from_value::<PublishDiagnosticsParams>(
+                            n.params.clone(),
+                        ).unwrap()
+                    ),
+                _ => None,
+            })
+            .expect("diagnostics are published");
+
+        assert_eq!(diagnostics.uri, uri);
+        assert_eq!(diagnostics.diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics.diagnostics[0].range,
+            Range::new(Position::new(3, 0), Position::new(4, 0)),
+        );
+
+        let actions = messages.iter()
+            .find_map(|m| match m {
+                Message::Response(r) if r.id == RequestId::from(2) =>
+                    Some(
+                        serde_json::from_value::<Vec<CodeActionOrCommand>>(
+                            r.result.clone().unwrap(),
+                        ).unwrap()
+                    ),
+                _ => None,
+            })
+            .expect("code actions are returned");
+
+        assert_eq!(actions.len(), 1);
+
+        let action = match &actions[0] {
+            CodeActionOrCommand::CodeAction(a) => a,
+            c => panic!("unexpected command {:?}", c),
+        };
+        let edits = &action.edit.as_ref().unwrap()
+            .changes.as_ref().unwrap()[&uri];
+
+        assert_eq!(
+            edits[0],
+            TextEdit {
+                range: Range::new(Position::new(3, 0), Position::new(4, 0)),
+                new_text: "Did gyre and gimble in the wabe;\n".to_owned(),
+            },
+        );
+    }
+
+    #[test]
+    fn run_rejects_invalid_params_and_keeps_serving() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        let mut input = initialize();
+        input.extend(vec![
+            Notification::new(
+                notification::DidOpenTextDocument::METHOD.to_owned(),
+                serde_json::json!({ "textDocument": 1 }),
+            ).into(),
+            Request::new(
+                RequestId::from(2),
+                request::CodeActionRequest::METHOD.to_owned(),
+                serde_json::json!({ "range": "Jabberwocky.txt" }),
+            ).into(),
+        ]);
+        input.extend(shut_down(3));
+
+        let messages = serve(&repo, &NoSuggestions, input);
+
+        let log = messages.iter()
+            .find_map(|m| match m {
+                Message::Notification(n)
+                    if n.method == notification::LogMessage::METHOD =>
+                    Some(
+                        serde_json::from_value::<LogMessageParams>(
+                            n.params.clone(),
+                        ).unwrap()
+                    ),
+                _ => None,
+            })
+            .expect("invalid notification is logged");
+
+        assert_eq!(log.typ, MessageType::WARNING);
+        assert!(log.message.contains(notification::DidOpenTextDocument::METHOD));
+
+        let error = messages.iter()
+            .find_map(|m| match m {
+                Message::Response(r) if r.id == RequestId::from(2) =>
+                    r.error.clone(),
+                _ => None,
+            })
+            .expect("invalid request gets an error");
+
+        assert_eq!(error.code, INVALID_PARAMS);
+
+        // The server still answers the shutdown request.
+        assert!(messages.iter().any(|m| match m {
+            Message::Response(r) => r.id == RequestId::from(3),
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn line_offset_finds_line_starts() {
+        let text = "’Twas brillig\nDid gyre\nAll mimsy";
+
+        assert_eq!(line_offset(text, 0), 0);
+        assert_eq!(line_offset(text, 1), "’Twas brillig\n".len());
+        assert_eq!(line_offset(text, 2), "’Twas brillig\nDid gyre\n".len());
+        assert_eq!(line_offset(text, 3), text.len());
+    }
+}
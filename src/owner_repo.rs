@@ -18,7 +18,6 @@ use std::str::FromStr;
 
 use git2::Repository;
 use thiserror::Error;
-use url;
 use url::Url;
 
 
@@ -33,6 +32,9 @@ pub enum Error {
 
     #[error("Unable to find remote '{0}'")]
     NoRemote(String),
+
+    #[error("No remote points at {0}")]
+    NoForgeRemote(String),
 }
 
 /// Errors parsing an `OwnerRepo`.
@@ -41,6 +43,9 @@ pub enum OwnerRepoError {
     #[error("Unable to parse URL")]
     Url(#[from] url::ParseError),
 
+    #[error("URL has no host")]
+    NoHost,
+
     #[error("URL has no path")]
     NoPath,
 
@@ -49,8 +54,12 @@ pub enum OwnerRepoError {
 }
 
 
+/// The host that remotes must point at to be detected automatically.
+const FORGE_HOST: &str = "github.com";
+
 #[derive(Debug)]
 pub struct OwnerRepo {
+    pub host: String,
     pub owner: String,
     pub repo: String,
 }
@@ -81,6 +90,9 @@ impl FromStr for OwnerRepo {
             .unwrap_or(path[1]);
 
         Ok(OwnerRepo {
+            host: url.host_str()
+                .ok_or(OwnerRepoError::NoHost)?
+                .to_owned(),
             owner: path[0].to_owned(),
             repo: repo.to_owned(),
         })
@@ -88,18 +100,78 @@ impl FromStr for OwnerRepo {
 }
 
 impl OwnerRepo {
-    /// Parse an `OwnerRepo` from the URL for `remote_name` in the current
-    /// repository.
-    pub fn from_remote(
-        remote_name: Option<&str>,
-    ) -> Result<OwnerRepo, Error> {
-        let repo = Repository::open(".")?;
+    /// Get the candidate `OwnerRepo`s for suggestion IDs in `repo`, in
+    /// priority order.
+    ///
+    /// If `remote_names` is empty, the remotes are detected with
+    /// `OwnerRepo::detect()`.
+    pub fn from_remotes(
+        repo: &Repository,
+        remote_names: &[String],
+    ) -> Result<Vec<OwnerRepo>, Error> {
+        if remote_names.is_empty() {
+            return OwnerRepo::detect(repo);
+        }
 
-        let remote_name = match remote_name {
-            Some(r) => r,
-            None => "origin",
-        };
+        remote_names.iter()
+            .map(|name| OwnerRepo::from_remote(repo, name))
+            .collect()
+    }
+
+    /// Find the remotes in `repo` that point at the forge host.
+    ///
+    /// The remote in `remote.pushDefault` comes first, followed by the
+    /// current branch's upstream remote, and then all other remotes.
+    pub fn detect(repo: &Repository) -> Result<Vec<OwnerRepo>, Error> {
+        let config = repo.config()?;
+
+        let mut names = Vec::new();
+
+        match config.get_string("remote.pushDefault") {
+            Ok(name) => names.push(name),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        if let Some(name) = upstream_remote(repo, &config)? {
+            names.push(name);
+        }
+
+        for name in repo.remotes()?.iter().flatten() {
+            names.push(name.to_owned());
+        }
+
+        let mut owner_repos = Vec::new();
+        let mut seen = Vec::new();
 
+        for name in names {
+            if seen.contains(&name) {
+                continue;
+            }
+
+            // Skip remotes that can't be parsed, as they can't point at the
+            // forge.
+            if let Ok(o_r) = OwnerRepo::from_remote(repo, &name) {
+                if o_r.host == FORGE_HOST {
+                    owner_repos.push(o_r);
+                }
+            }
+
+            seen.push(name);
+        }
+
+        if owner_repos.is_empty() {
+            return Err(Error::NoForgeRemote(FORGE_HOST.to_owned()));
+        }
+
+        Ok(owner_repos)
+    }
+
+    /// Parse an `OwnerRepo` from the URL for `remote_name` in `repo`.
+    pub fn from_remote(
+        repo: &Repository,
+        remote_name: &str,
+    ) -> Result<OwnerRepo, Error> {
         let remote = repo.find_remote(remote_name)?;
         let url = remote.url()
             .ok_or_else(|| Error::NoRemote(remote_name.to_owned()))?;
@@ -114,6 +186,12 @@ impl OwnerRepo {
         let path = address_path.get(1)
             .ok_or(OwnerRepoError::NoOwnerRepo)?;
 
+        let address = address_path[0];
+        let host = match address.rsplit_once('@') {
+            Some((_user, host)) => host,
+            None => address,
+        };
+
         let path = path
             .strip_suffix(".git")
             .unwrap_or(path);
@@ -125,8 +203,89 @@ impl OwnerRepo {
         }
 
         Ok(OwnerRepo {
+            host: host.to_owned(),
             owner: segments[0].to_owned(),
             repo: segments[1].to_owned(),
         })
     }
 }
+
+
+/// Get the name of the remote of the current branch's upstream in `repo`.
+///
+/// Returns `Ok(None)` if `HEAD` is detached or the branch has no upstream.
+fn upstream_remote(
+    repo: &Repository,
+    config: &git2::Config,
+) -> Result<Option<String>, git2::Error> {
+    let head = match repo.head() {
+        Ok(h) => h,
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    if !head.is_branch() {
+        return Ok(None);
+    }
+
+    let branch = match head.shorthand() {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+
+    match config.get_string(&format!("branch.{}.remote", branch)) {
+        Ok(r) => Ok(Some(r)),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_repo_parses_host() {
+        let https: OwnerRepo = "https://github.com/teddywing/git-suggestion.git"
+            .parse()
+            .unwrap();
+        let ssh: OwnerRepo = "git@github.com:teddywing/git-suggestion.git"
+            .parse()
+            .unwrap();
+
+        for o_r in &[https, ssh] {
+            assert_eq!(o_r.host, "github.com");
+            assert_eq!(o_r.owner, "teddywing");
+            assert_eq!(o_r.repo, "git-suggestion");
+        }
+    }
+
+    #[test]
+    fn detect_finds_forge_remotes_in_priority_order() {
+        use tempfile::tempdir;
+
+
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        repo.remote("fork", "git@github.com:oshino/git-suggestion.git")
+            .unwrap();
+        repo.remote("mirror", "https://gitlab.com/oshino/git-suggestion.git")
+            .unwrap();
+        repo.remote(
+            "upstream",
+            "https://github.com/teddywing/git-suggestion.git",
+        ).unwrap();
+        repo.config().unwrap()
+            .set_str("remote.pushDefault", "upstream")
+            .unwrap();
+
+        let owner_repos = OwnerRepo::detect(&repo).unwrap();
+        let owners: Vec<_> = owner_repos.iter()
+            .map(|o_r| o_r.owner.as_str())
+            .collect();
+
+        assert_eq!(owners, vec!["teddywing", "oshino"]);
+    }
+}
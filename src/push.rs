@@ -0,0 +1,225 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::cell::RefCell;
+
+use git2::{Cred, CredentialType, PushOptions, RemoteCallbacks, Repository};
+use thiserror::Error;
+
+use github_suggestion::{client, url, Client, Suggestion};
+use github_suggestion::github::PullRequest;
+use github_suggestion::url::PullUrl;
+
+
+/// Errors pushing suggestions to a pull request branch.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Client(#[from] client::Error),
+
+    #[error(transparent)]
+    Url(#[from] url::Error),
+
+    #[error("Suggestion {0} has no pull request URL")]
+    NoPullRequest(String),
+
+    #[error("The suggestions are from several pull requests")]
+    SeveralPullRequests,
+
+    #[error("The head repository of pull request #{0} was deleted")]
+    HeadRepoDeleted(u64),
+
+    #[error("Pull request #{pull} doesn't allow edits from maintainers, ask the author of {repo} to enable them")]
+    MaintainerEditsDisabled {
+        pull: u64,
+        repo: String,
+    },
+
+    #[error("Push to '{reference}' was rejected: {message}")]
+    Rejected {
+        reference: String,
+        message: String,
+    },
+}
+
+
+/// Push `local_ref` to the head branch of the pull request that
+/// `suggestions` are from, in the repository in the current environment.
+pub fn push(
+    suggestions: &[Suggestion],
+    local_ref: &str,
+    token: &str,
+) -> Result<(), Error> {
+    let repo = Repository::open_from_env()?;
+
+    push_with_repo(suggestions, local_ref, token, &repo)
+}
+
+/// Push `local_ref` in `repo` to the head branch of the pull request that
+/// `suggestions` are from.
+///
+/// The head branch is looked up with the GitHub API, and can be in a fork.
+/// The push uses `token` for HTTPS authentication, and is not forced.
+pub fn push_with_repo(
+    suggestions: &[Suggestion],
+    local_ref: &str,
+    token: &str,
+    repo: &Repository,
+) -> Result<(), Error> {
+    let pull_url = pull_url(suggestions)?;
+    let client = Client::new(token, &pull_url.owner, &pull_url.repo)?;
+    let pull = client.pull_request(&pull_url.number)?;
+
+    let (url, branch) = push_target(&pull)?;
+
+    let local_ref = repo.resolve_reference_from_short_name(local_ref)?;
+    let refspec = format!(
+        "{}:refs/heads/{}",
+        local_ref.name().unwrap_or("HEAD"),
+        branch,
+    );
+
+    let rejection = RefCell::new(None);
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Cred::userpass_plaintext("x-access-token", token);
+        }
+
+        Cred::credential_helper(&repo.config()?, url, username)
+    });
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(message) = status {
+            *rejection.borrow_mut() = Some(Error::Rejected {
+                reference: reference.to_owned(),
+                message: message.to_owned(),
+            });
+        }
+
+        Ok(())
+    });
+
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+
+    let mut remote = repo.remote_anonymous(url)?;
+    remote.push(&[&refspec], Some(&mut options))?;
+    drop(options);
+
+    match rejection.into_inner() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Get the URL of the pull request that all of `suggestions` are from.
+fn pull_url(suggestions: &[Suggestion]) -> Result<PullUrl, Error> {
+    let mut pull_url: Option<PullUrl> = None;
+
+    for suggestion in suggestions {
+        let url: PullUrl = suggestion.url.as_ref()
+            .ok_or_else(|| Error::NoPullRequest(suggestion.id.clone()))?
+            .parse()?;
+
+        match &pull_url {
+            Some(p) if (&p.host, &p.owner, &p.repo, &p.number)
+                != (&url.host, &url.owner, &url.repo, &url.number) =>
+                return Err(Error::SeveralPullRequests),
+            Some(_) => (),
+            None => pull_url = Some(url),
+        }
+    }
+
+    pull_url.ok_or_else(|| Error::NoPullRequest(String::new()))
+}
+
+/// Get the clone URL and branch name of the head of `pull`.
+///
+/// Branches in forks can only be pushed to if the pull request allows edits
+/// from maintainers.
+fn push_target(pull: &PullRequest) -> Result<(&str, &str), Error> {
+    let head_repo = pull.head.repo.as_ref()
+        .ok_or(Error::HeadRepoDeleted(pull.number))?;
+
+    let is_fork = pull.base.repo.as_ref()
+        .map_or(true, |base| base.full_name != head_repo.full_name);
+
+    if is_fork && !pull.maintainer_can_modify {
+        return Err(Error::MaintainerEditsDisabled {
+            pull: pull.number,
+            repo: head_repo.full_name.clone(),
+        });
+    }
+
+    Ok((&head_repo.clone_url, &pull.head.name))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use github_suggestion::github::{Branch, Repository};
+
+    fn branch(name: &str, repo: &str) -> Branch {
+        Branch {
+            name: name.to_owned(),
+            repo: Some(Repository {
+                full_name: repo.to_owned(),
+                clone_url: format!("https://github.com/{}.git", repo),
+            }),
+        }
+    }
+
+    fn pull_request(
+        head_repo: &str,
+        maintainer_can_modify: bool,
+    ) -> PullRequest {
+        PullRequest {
+            number: 1,
+            head: branch("vorpal-sword", head_repo),
+            base: branch("master", "teddywing/git-suggestion"),
+            maintainer_can_modify,
+        }
+    }
+
+    #[test]
+    fn push_target_requires_maintainer_edits_on_forks() {
+        let same_repo = pull_request("teddywing/git-suggestion", false);
+        assert_eq!(
+            push_target(&same_repo).unwrap(),
+            ("https://github.com/teddywing/git-suggestion.git", "vorpal-sword"),
+        );
+
+        let fork = pull_request("oshino-shinobu/git-suggestion", true);
+        assert_eq!(
+            push_target(&fork).unwrap().0,
+            "https://github.com/oshino-shinobu/git-suggestion.git",
+        );
+
+        let locked_fork = pull_request("oshino-shinobu/git-suggestion", false);
+        match push_target(&locked_fork) {
+            Err(Error::MaintainerEditsDisabled { pull: 1, repo }) =>
+                assert_eq!(repo, "oshino-shinobu/git-suggestion"),
+            r => panic!("expected maintainer edits error, got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use git2::Repository;
+use thiserror::Error;
+
+use github_suggestion::Suggestion;
+
+use crate::status::{self, working_tree_line};
+
+
+/// Errors formatting a suggestion as a quickfix line.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Status(#[from] status::Error),
+}
+
+
+/// Format `suggestion` as a quickfix line for the repository in the current
+/// environment.
+pub fn quickfix(suggestion: &Suggestion) -> Result<Option<String>, Error> {
+    let repo = Repository::open_from_env()?;
+
+    quickfix_with_repo(suggestion, &repo)
+}
+
+/// Format `suggestion` as a quickfix line in the format
+/// `path:line:col: author: message`, which editors' error format parsers
+/// understand.
+///
+/// The line is where the suggestion is in the working tree of `repo`, and the
+/// message is the first line of the comment text. Returns `None` if the
+/// suggestion's lines can't be found in the working tree.
+pub fn quickfix_with_repo(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<Option<String>, Error> {
+    let line = working_tree_line(suggestion, repo)?;
+
+    Ok(line.map(|line| format_line(suggestion, line)))
+}
+
+/// Build the quickfix line for `suggestion` at `line`.
+fn format_line(suggestion: &Suggestion, line: usize) -> String {
+    format!(
+        "{}:{}:1: {}: {}",
+        suggestion.path,
+        line,
+        suggestion.author.name,
+        summary(suggestion),
+    )
+}
+
+/// Get the first line of the suggestion's comment text, or "suggestion" if
+/// the comment only has a suggestion block.
+pub fn summary(suggestion: &Suggestion) -> &str {
+    match suggestion.message.lines().next() {
+        Some(m) if !m.trim().is_empty() => m.trim(),
+        _ => "suggestion",
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use github_suggestion::{Author, Side};
+
+    #[test]
+    fn format_line_uses_first_line_of_message() {
+        let suggestion = Suggestion {
+            id: "1".to_owned(),
+            review_id: None,
+            path: "poems/Jabberwocky.txt".to_owned(),
+            commit: String::new(),
+            start_line: 4,
+            end_line: 5,
+            side: Side::Right,
+            message: "Use the sword.\n\nThe blade is too dull.".to_owned(),
+            replacement: String::new(),
+            author: Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        };
+
+        assert_eq!(
+            format_line(&suggestion, 6),
+            "poems/Jabberwocky.txt:6:1: oshino-shinobu: Use the sword.",
+        );
+    }
+}
@@ -0,0 +1,319 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use git2::Repository;
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+use github_suggestion::{suggestion, Suggestion};
+
+
+/// Errors undoing a suggestion.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Suggestion(#[from] suggestion::Error),
+
+    #[error("{message}: {source}")]
+    Io {
+        source: std::io::Error,
+        message: String,
+    },
+
+    #[error("Repository has no working tree")]
+    Bare,
+
+    #[error("git-cat-file failed on {0}")]
+    CatFile(String),
+
+    #[error("git-merge-file failed on {0}")]
+    MergeFile(String),
+
+    #[error("Reverting the suggestion left conflicts in {0}")]
+    Conflict(String),
+}
+
+
+/// Undo `suggestion` in the working tree of the repository in the current
+/// environment.
+pub fn revert(suggestion: &Suggestion) -> Result<(), Error> {
+    let repo = Repository::open_from_env()?;
+
+    revert_with_repo(suggestion, &repo)
+}
+
+/// Undo `suggestion` in the working tree of `repo`.
+///
+/// The reverse of the suggestion diff is applied to the file. If the lines
+/// around the suggestion have changed since it was applied, fall back to a
+/// 3-way merge between the file, the suggested version, and the original
+/// version. Conflicts are left in the file with conflict markers.
+pub fn revert_with_repo(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<(), Error> {
+    match suggestion.unapply_at(repo, git2::ApplyLocation::WorkDir) {
+        Err(suggestion::Error::Git(e))
+            if e.code() == git2::ErrorCode::ApplyFail => (),
+        r => return Ok(r?),
+    }
+
+    merge(suggestion, repo)
+}
+
+/// Undo `suggestion` in the working tree of `repo` with `git merge-file`,
+/// using the suggested version of the file as the merge base.
+///
+/// The versions are merged as they would be checked out, so that they have
+/// the same line endings and encoding as the file.
+fn merge(suggestion: &Suggestion, repo: &Repository) -> Result<(), Error> {
+    let workdir = repo.workdir().ok_or(Error::Bare)?;
+    let path = workdir.join(&suggestion.path);
+
+    let original = suggestion.original_blob_with_repo(repo)?.id();
+    let suggested = suggestion.blob_with_repo(repo)?;
+
+    let base = temp_file(
+        &checkout_content(repo, workdir, &suggestion.path, suggested)?,
+    )?;
+    let other = temp_file(
+        &checkout_content(repo, workdir, &suggestion.path, original)?,
+    )?;
+
+    let status = Command::new("git")
+        .arg("merge-file")
+        .args(["-L", "current", "-L", "suggestion", "-L", "original"])
+        .arg(&path)
+        .arg(base.path())
+        .arg(other.path())
+        .status()
+        .map_err(|e| Error::Io {
+            source: e,
+            message: "Unable to run git-merge-file".to_owned(),
+        })?;
+
+    // git-merge-file exits with the number of conflicts, or a negative
+    // status on error.
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) if (1..128).contains(&code) =>
+            Err(Error::Conflict(suggestion.path.clone())),
+        _ => Err(Error::MergeFile(suggestion.path.clone())),
+    }
+}
+
+/// Get the contents of `blob` as they would be checked out to `path` in
+/// `workdir`, with the checkout filters for the path applied.
+fn checkout_content(
+    repo: &Repository,
+    workdir: &Path,
+    path: &str,
+    blob: git2::Oid,
+) -> Result<Vec<u8>, Error> {
+    // Attributes are looked up relative to the current directory.
+    let output = Command::new("git")
+        .current_dir(workdir)
+        .arg("--git-dir")
+        .arg(repo.path())
+        .arg("--work-tree")
+        .arg(workdir)
+        .arg("cat-file")
+        .arg("--filters")
+        .arg(format!("--path={}", path))
+        .arg(blob.to_string())
+        .output()
+        .map_err(|e| Error::Io {
+            source: e,
+            message: "Unable to run git-cat-file".to_owned(),
+        })?;
+
+    if !output.status.success() {
+        return Err(Error::CatFile(path.to_owned()));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Write `content` to a new temporary file.
+fn temp_file(content: &[u8]) -> Result<NamedTempFile, Error> {
+    let io_error = |e| Error::Io {
+        source: e,
+        message: "Unable to write temporary file".to_owned(),
+    };
+
+    let mut file = NamedTempFile::new().map_err(io_error)?;
+    file.write_all(content).map_err(io_error)?;
+
+    Ok(file)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    use github_suggestion::{Author, Side};
+    use tempfile::tempdir;
+
+    const ORIGINAL: &str = "’Twas brillig, and the slithy toves\n\
+        Did gyre and gimble in the wabe:\n\
+        All mimsy were the borogoves,\n\
+        He took his vorpal blade in hand:\n\
+        And the mome raths outgrabe.\n\
+        Long time the manxome foe he sought--\n\
+        So rested he by the Tumtum tree,\n";
+
+    /// Commit `ORIGINAL` in a new repository with a working tree.
+    fn repo() -> (tempfile::TempDir, Repository, git2::Oid) {
+        repo_with_attributes("", ORIGINAL)
+    }
+
+    /// Commit `ORIGINAL` in a new repository with `attributes` in its
+    /// `.gitattributes`, and leave `checked_out` in the working tree.
+    fn repo_with_attributes(
+        attributes: &str,
+        checked_out: &str,
+    ) -> (tempfile::TempDir, Repository, git2::Oid) {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+
+        fs::write(git_root.path().join(".gitattributes"), attributes).unwrap();
+        fs::write(git_root.path().join("Jabberwocky.txt"), checked_out)
+            .unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(".gitattributes")).unwrap();
+        index.add_path(Path::new("Jabberwocky.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let signature = git2::Signature::now(
+            "Oshino Shinobu",
+            "oshino.shinobu@example.com",
+        ).unwrap();
+        let commit = repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Add poem",
+            &tree,
+            &[],
+        ).unwrap();
+        drop(tree);
+
+        (git_root, repo, commit)
+    }
+
+    fn suggestion(commit: git2::Oid) -> Suggestion {
+        Suggestion {
+            id: "1".to_owned(),
+            review_id: None,
+            path: "Jabberwocky.txt".to_owned(),
+            commit: commit.to_string(),
+            start_line: 4,
+            end_line: 4,
+            side: Side::Right,
+            message: String::new(),
+            replacement: "He took his vorpal sword in hand:\n".to_owned(),
+            author: Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: None,
+        }
+    }
+
+    #[test]
+    fn revert_with_repo_merges_when_nearby_lines_changed() {
+        let (git_root, repo, commit) = repo();
+        let path = git_root.path().join("Jabberwocky.txt");
+        let suggestion = suggestion(commit);
+
+        suggestion.apply_with_repo(&repo).unwrap();
+        revert_with_repo(&suggestion, &repo).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), ORIGINAL);
+
+        suggestion.apply_with_repo(&repo).unwrap();
+
+        let edited = fs::read_to_string(&path).unwrap()
+            .replace("manxome foe", "manxome Jabberwock");
+        fs::write(&path, &edited).unwrap();
+
+        revert_with_repo(&suggestion, &repo).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            ORIGINAL.replace("manxome foe", "manxome Jabberwock"),
+        );
+    }
+
+    #[test]
+    fn revert_with_repo_merges_with_checkout_line_endings() {
+        let crlf = ORIGINAL.replace('\n', "\r\n");
+        let (git_root, repo, commit) =
+            repo_with_attributes("*.txt text eol=crlf\n", &crlf);
+        let path = git_root.path().join("Jabberwocky.txt");
+        let suggestion = suggestion(commit);
+
+        suggestion.apply_with_repo(&repo).unwrap();
+
+        let edited = fs::read_to_string(&path).unwrap()
+            .replace("manxome foe", "manxome Jabberwock");
+        fs::write(&path, &edited).unwrap();
+
+        revert_with_repo(&suggestion, &repo).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            crlf.replace("manxome foe", "manxome Jabberwock"),
+        );
+    }
+
+    #[test]
+    fn revert_with_repo_reports_conflicts() {
+        let (git_root, repo, commit) = repo();
+        let path = git_root.path().join("Jabberwocky.txt");
+        let suggestion = suggestion(commit);
+
+        suggestion.apply_with_repo(&repo).unwrap();
+
+        let edited = fs::read_to_string(&path).unwrap()
+            .replace("vorpal sword", "vorpal sword and shield");
+        fs::write(&path, &edited).unwrap();
+
+        match revert_with_repo(&suggestion, &repo) {
+            Err(Error::Conflict(p)) => assert_eq!(p, "Jabberwocky.txt"),
+            r => panic!("expected conflict, got {:?}", r),
+        }
+
+        assert!(fs::read_to_string(&path).unwrap().contains("<<<<<<< current"));
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright (c) 2020  Teddy Wing
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+
+
+use std::fmt;
+use std::fs;
+use std::io;
+
+use git2::Repository;
+use thiserror::Error;
+
+use github_suggestion::{suggestion, Suggestion};
+use github_suggestion::suggestion::Location;
+
+
+/// Errors getting the status of a suggestion.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Suggestion(#[from] suggestion::Error),
+
+    #[error("{message}: {source}")]
+    Io {
+        source: io::Error,
+        message: String,
+    },
+}
+
+/// The state of a suggestion relative to the current branch.
+#[derive(Debug, PartialEq)]
+pub enum State {
+    /// The replacement lines are in `HEAD` or the working tree.
+    Applied,
+
+    /// The suggestion can be applied to the working tree.
+    Applies,
+
+    /// The lines that the suggestion replaces have changed.
+    Conflicts,
+
+    /// The lines that the comment was made on have changed in the pull
+    /// request since.
+    Outdated,
+
+    /// The comment's thread was resolved.
+    Resolved,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            State::Applied => "applied",
+            State::Applies => "applies",
+            State::Conflicts => "conflicts",
+            State::Outdated => "outdated",
+            State::Resolved => "resolved",
+        };
+
+        f.pad(state)
+    }
+}
+
+
+/// Get the state of `suggestion` in the repository in the current
+/// environment.
+pub fn status(suggestion: &Suggestion) -> Result<State, Error> {
+    let repo = Repository::open_from_env()?;
+
+    status_with_repo(suggestion, &repo)
+}
+
+/// Get the state of `suggestion` in `repo`.
+///
+/// A suggestion is applied if its replacement is found in `HEAD` or in the
+/// working tree. Otherwise, resolved and outdated comments are reported as
+/// such, and the rest either apply to the working tree or conflict with it.
+/// In a bare repository, `HEAD` is used instead of the working tree.
+pub fn status_with_repo(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<State, Error> {
+    let head = head_content(suggestion, repo)?;
+    let working_tree = working_tree_content(suggestion, repo)?;
+
+    let mut current = None;
+
+    for content in working_tree.iter().chain(&head) {
+        let location = suggestion.locate(repo, content)?;
+
+        if let Location::Applied(_) = location {
+            return Ok(State::Applied);
+        }
+
+        current.get_or_insert(location);
+    }
+
+    if suggestion.resolved == Some(true) {
+        return Ok(State::Resolved);
+    }
+
+    if suggestion.outdated {
+        return Ok(State::Outdated);
+    }
+
+    match current {
+        Some(Location::Original(_)) => Ok(State::Applies),
+        _ => Ok(State::Conflicts),
+    }
+}
+
+/// Get the line in the working tree of `repo` where the suggestion's lines
+/// start, following them if lines were added or removed above them.
+///
+/// Returns `None` if the lines can't be found in the working tree. If there
+/// is no working tree, the suggestion's own start line is used.
+pub fn working_tree_line(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<Option<usize>, Error> {
+    if repo.workdir().is_none() {
+        return Ok(Some(suggestion.start_line));
+    }
+
+    let content = match working_tree_content(suggestion, repo)? {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+
+    match suggestion.locate(repo, &content)? {
+        Location::Original(line) | Location::Applied(line) => Ok(Some(line)),
+        Location::Missing => Ok(None),
+    }
+}
+
+/// Get the content of the suggestion's file in `HEAD`, if it exists.
+fn head_content(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<Option<Vec<u8>>, Error> {
+    let tree = match repo.head() {
+        Ok(head) => head.peel_to_tree()?,
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let entry = match tree.get_path(suggestion.path.as_ref()) {
+        Ok(entry) => entry,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(
+        entry.to_object(repo)?
+            .as_blob()
+            .map(|blob| blob.content().to_vec())
+    )
+}
+
+/// Get the content of the suggestion's file in the working tree of `repo`,
+/// if it exists.
+fn working_tree_content(
+    suggestion: &Suggestion,
+    repo: &Repository,
+) -> Result<Option<Vec<u8>>, Error> {
+    let workdir = match repo.workdir() {
+        Some(workdir) => workdir,
+        None => return Ok(None),
+    };
+
+    let path = workdir.join(&suggestion.path);
+
+    match fs::read(&path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::Io {
+            source: e,
+            message: format!("Unable to read '{}'", path.display()),
+        }),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use github_suggestion::{Author, Side};
+    use tempfile::tempdir;
+
+    const ORIGINAL: &str = "’Twas brillig, and the slithy toves\n\
+        Did gyre and gimble in the wabe:\n\
+        All mimsy were the borogoves,\n\
+        He took his vorpal blade in hand:\n\
+        And the mome raths outgrabe.\n\
+        Long time the manxome foe he sought--\n\
+        So rested he by the Tumtum tree,\n";
+
+    #[test]
+    fn status_with_repo_reports_suggestion_states() {
+        let git_root = tempdir().unwrap();
+        let repo = Repository::init(git_root.path()).unwrap();
+        let path = git_root.path().join("Jabberwocky.txt");
+
+        fs::write(&path, ORIGINAL).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("Jabberwocky.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let signature = git2::Signature::now(
+            "Oshino Shinobu",
+            "oshino.shinobu@example.com",
+        ).unwrap();
+        let commit = repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Add poem",
+            &tree,
+            &[],
+        ).unwrap();
+
+        let mut suggestion = Suggestion {
+            id: "1".to_owned(),
+            review_id: None,
+            path: "Jabberwocky.txt".to_owned(),
+            commit: commit.to_string(),
+            start_line: 4,
+            end_line: 4,
+            side: Side::Right,
+            message: String::new(),
+            replacement: "He took his vorpal sword in hand:\n".to_owned(),
+            author: Author {
+                name: "oshino-shinobu".to_owned(),
+                email: "1+oshino-shinobu@users.noreply.github.com".to_owned(),
+            },
+            created_at: "2020-07-23T17:20:29Z".to_owned(),
+            url: None,
+            outdated: false,
+            resolved: Some(false),
+        };
+
+        assert_eq!(status_with_repo(&suggestion, &repo).unwrap(), State::Applies);
+
+        suggestion.outdated = true;
+        assert_eq!(
+            status_with_repo(&suggestion, &repo).unwrap(),
+            State::Outdated,
+        );
+
+        suggestion.resolved = Some(true);
+        assert_eq!(
+            status_with_repo(&suggestion, &repo).unwrap(),
+            State::Resolved,
+        );
+
+        suggestion.outdated = false;
+        suggestion.resolved = Some(false);
+
+        fs::write(&path, ORIGINAL.replace("blade in", "blade at")).unwrap();
+        assert_eq!(
+            status_with_repo(&suggestion, &repo).unwrap(),
+            State::Conflicts,
+        );
+        assert_eq!(working_tree_line(&suggestion, &repo).unwrap(), None);
+
+        fs::write(&path, format!("Jabberwocky\n\n{}", ORIGINAL)).unwrap();
+        assert_eq!(working_tree_line(&suggestion, &repo).unwrap(), Some(6));
+
+        fs::write(&path, ORIGINAL.replace("blade", "sword")).unwrap();
+        assert_eq!(status_with_repo(&suggestion, &repo).unwrap(), State::Applied);
+    }
+}
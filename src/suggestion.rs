@@ -14,88 +14,694 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 
-use std::process;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-use exitcode;
+use github_suggestion::{client, gitea, Client, Fetch, Suggestion, SuggestionUrl};
+use github_suggestion::url::{Forge, PullUrl, Target};
 
-use github_suggestion::{Client, Suggestion, SuggestionUrl};
-
-use crate::gseprintln;
 use crate::arg::is_suggestion_id;
 use crate::config::Config;
+use crate::current::current_pull_request_with_repo;
+use crate::error::Error;
+use crate::owner_repo::OwnerRepo;
+use crate::status::{status_with_repo, State};
+
 
+/// Maximum number of suggestions to fetch at the same time.
+const MAX_WORKERS: usize = 8;
+
+/// Fetch all suggestions in `config.suggestions` and read those in
+/// `config.json_files`, with a result for each.
+///
+/// Suggestions are fetched concurrently, and all of them are fetched before
+/// this returns. They are in the same order as `config.suggestions`,
+/// followed by the suggestions from the JSON files.
+pub fn suggestions(config: &Config) -> Vec<Result<Suggestion, Error>> {
+    fetch_all(config)
+}
 
 /// For all suggestions in `config.suggestions`, fetch the suggestion from the
 /// API and call `f` with it.
-pub fn for_suggestion<F>(config: &Config, f: F)
-where F: Fn(&Suggestion)
+///
+/// Stops at the first error, unless `config.keep_going` is set. In that case,
+/// all suggestions are processed, and the errors are returned at the end.
+pub fn for_suggestion<F>(config: &Config, mut f: F) -> Result<(), Error>
+where F: FnMut(&Suggestion) -> Result<(), Error>
 {
-    for suggestion_arg in &config.suggestions {
-        let suggestion = if match is_suggestion_id(&suggestion_arg) {
-            Ok(p) => p,
-            Err(e) => {
-                gseprintln!(e);
+    let mut errors = Vec::new();
 
-                process::exit(exitcode::SOFTWARE);
+    for suggestion in suggestions(config) {
+        if let Err(e) = suggestion.and_then(|s| f(&s)) {
+            if !config.keep_going {
+                return Err(e);
             }
-        } {
-            let o_r = match &config.o_r {
-                Ok(o_r) => o_r,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::CONFIG);
-                },
-            };
-
-            let client = match Client::new(
-                &config.github_token,
-                &o_r.owner,
-                &o_r.repo,
-            ) {
-                Ok(c) => c,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::SOFTWARE);
-                },
-            };
-
-            match client.fetch(&suggestion_arg) {
-                Ok(s) => s,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::UNAVAILABLE);
-                },
+
+            errors.push(e);
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::Multiple(errors)),
+    }
+}
+
+/// Fetch all suggestions on the pull requests in `config.suggestions`, and
+/// read those in `config.json_files`.
+///
+/// A pull request is referenced either by its URL, or by its number on the
+/// repository of the first candidate remote that has it. A reference of `-`
+/// reads review comment JSON from standard input.
+pub fn pull_suggestions(config: &Config) -> Result<Vec<Suggestion>, Error> {
+    let clients = Clients::default();
+    let mut o_r = None;
+    let mut suggestions = Vec::new();
+
+    for pull_arg in &config.suggestions {
+        if pull_arg == "-" {
+            suggestions.extend(read_json(pull_arg)?);
+
+            continue;
+        }
+
+        let repos = if is_suggestion_id(pull_arg)? {
+            if o_r.is_none() {
+                o_r = Some(config.owner_repos()?);
             }
+
+            o_r.as_ref()
+                .expect("owner repos were initialised")
+                .iter()
+                .map(|o_r| (
+                    (o_r.host.clone(), o_r.owner.clone(), o_r.repo.clone()),
+                    pull_arg.clone(),
+                ))
+                .collect()
         } else {
-            let url: SuggestionUrl = match suggestion_arg.parse() {
-                Ok(u) => u,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::USAGE);
-                },
-            };
-
-            let client = match Client::new(
-                &config.github_token,
-                &url.owner,
-                &url.repo,
-            ) {
-                Ok(c) => c,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::SOFTWARE);
-                },
-            };
-
-            match client.fetch(&url.comment_id) {
-                Ok(s) => s,
-                Err(e) => {
-                    gseprintln!(e);
-                    process::exit(exitcode::UNAVAILABLE);
-                },
+            let url: PullUrl = pull_arg.parse()?;
+
+            vec![((url.host, url.owner, url.repo), url.number)]
+        };
+
+        suggestions.extend(list(config, &clients, repos)?);
+    }
+
+    for path in &config.json_files {
+        suggestions.extend(read_json(path)?);
+    }
+
+    suggestions.retain(|s| config.filter.matches(s));
+
+    Ok(suggestions)
+}
+
+/// Fetch the suggestions on the pull request at `pull_url` that match
+/// `config.filter`.
+pub fn pull_request_suggestions(
+    config: &Config,
+    pull_url: &str,
+) -> Result<Vec<Suggestion>, Error> {
+    pull_request_suggestions_with_clients(config, &Clients::default(), pull_url)
+}
+
+/// Fetch the suggestions on the pull request at `pull_url` that match
+/// `config.filter`, reusing or adding a client in `clients`.
+fn pull_request_suggestions_with_clients(
+    config: &Config,
+    clients: &Clients,
+    pull_url: &str,
+) -> Result<Vec<Suggestion>, Error> {
+    let url: PullUrl = pull_url.parse()?;
+    let pulls = vec![((url.host, url.owner, url.repo), url.number)];
+
+    let mut suggestions = list(config, clients, pulls)?;
+    suggestions.retain(|s| config.filter.matches(s));
+
+    Ok(suggestions)
+}
+
+/// List the suggestions on the pull request in the first of `pulls`, given
+/// as repository and number, that exists, reusing or adding a client in
+/// `clients` for each repository.
+///
+/// Errors other than a missing pull request are returned immediately. If
+/// none of them exist, return the error from the first one.
+fn list(
+    config: &Config,
+    clients: &Clients,
+    pulls: Vec<(Repo, String)>,
+) -> Result<Vec<Suggestion>, Error> {
+    let mut first_error = None;
+
+    for (repo, number) in pulls {
+        let client = clients.github(config, &repo)?;
+
+        match client.list(&number) {
+            Ok(suggestions) => return Ok(suggestions),
+            Err(e @ client::Error::NotFound(_)) => {
+                first_error.get_or_insert(e);
+            },
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(
+        first_error
+            .expect("pull request has at least one candidate repository")
+            .into()
+    )
+}
+
+/// A repository that a comment can be fetched from.
+type Repo = (String, String, String);
+
+/// API clients shared between fetch workers, by repository.
+///
+/// Each client keeps its connections and event loop open for later requests.
+#[derive(Default)]
+struct Clients {
+    /// Clients to fetch comments with, by repository and forge.
+    fetchers: Mutex<HashMap<(Repo, Forge), Arc<dyn Fetch>>>,
+
+    /// GitHub clients, by repository.
+    github: Mutex<HashMap<Repo, Arc<Client>>>,
+}
+
+impl Clients {
+    /// Get the GitHub client for `repo`, adding it if there isn't one yet.
+    fn github(
+        &self,
+        config: &Config,
+        repo: &Repo,
+    ) -> Result<Arc<Client>, Error> {
+        let mut github = self.github.lock()
+            .expect("GitHub clients lock is not poisoned");
+
+        if let Some(client) = github.get(repo) {
+            return Ok(Arc::clone(client));
+        }
+
+        let client = Arc::new(
+            Client::new(config.require_github_token()?, &repo.1, &repo.2)?,
+        );
+        github.insert(repo.clone(), Arc::clone(&client));
+
+        Ok(client)
+    }
+
+    /// Get the client to fetch comments from `repo` on `forge`, adding it if
+    /// there isn't one yet.
+    fn fetcher(
+        &self,
+        config: &Config,
+        repo: Repo,
+        forge: Forge,
+    ) -> Result<Arc<dyn Fetch>, Error> {
+        let key = (repo, forge);
+        let mut fetchers = self.fetchers.lock()
+            .expect("fetchers lock is not poisoned");
+
+        if let Some(client) = fetchers.get(&key) {
+            return Ok(Arc::clone(client));
+        }
+
+        let client: Arc<dyn Fetch> = match &key.1 {
+            Forge::Github => self.github(config, &key.0)?,
+            Forge::Gitea { base_url, pull } =>
+                Arc::new(gitea_client(config, &key.0, base_url, pull)?),
+        };
+        fetchers.insert(key, Arc::clone(&client));
+
+        Ok(client)
+    }
+}
+
+/// A comment to fetch from the first of a list of candidate repositories that
+/// has it.
+#[derive(Debug)]
+struct Comment {
+    repos: Vec<Repo>,
+    id: String,
+    forge: Forge,
+}
+
+/// A review whose suggestion comments to fetch.
+#[derive(Debug)]
+struct Review {
+    repo: Repo,
+    pull: String,
+    id: String,
+}
+
+/// Where to get the suggestions for a command line argument.
+#[derive(Debug)]
+enum Source {
+    /// Fetch the suggestion from the API.
+    Comment(Comment),
+
+    /// Fetch the suggestions in a review from the API.
+    Review(Review),
+
+    /// Suggestions that were read from JSON.
+    Json(Vec<Suggestion>),
+}
+
+/// Parse the suggestion source referenced by `suggestion_arg`, either a
+/// comment ID, a comment or review URL, or `-` to read JSON from standard
+/// input.
+///
+/// Comment IDs refer to the repositories of the candidate remotes in `o_r`,
+/// which are looked up on first use.
+fn parse_source(
+    config: &Config,
+    o_r: &mut Option<Vec<OwnerRepo>>,
+    suggestion_arg: &str,
+) -> Result<Source, Error> {
+    if suggestion_arg == "-" {
+        return Ok(Source::Json(read_json(suggestion_arg)?));
+    }
+
+    if is_suggestion_id(suggestion_arg)? {
+        if o_r.is_none() {
+            *o_r = Some(config.owner_repos()?);
+        }
+
+        let o_r = o_r.as_ref()
+            .expect("owner repos were initialised");
+
+        Ok(Source::Comment(Comment {
+            repos: o_r.iter()
+                .map(|o_r| (
+                    o_r.host.clone(),
+                    o_r.owner.clone(),
+                    o_r.repo.clone(),
+                ))
+                .collect(),
+            id: suggestion_arg.to_owned(),
+            forge: Forge::Github,
+        }))
+    } else {
+        let url: SuggestionUrl = suggestion_arg.parse()?;
+        let repo = (url.host, url.owner, url.repo);
+
+        match url.target {
+            Target::Comment(id) => Ok(Source::Comment(Comment {
+                repos: vec![repo],
+                id,
+                forge: url.forge,
+            })),
+            Target::Review { pull, id } =>
+                Ok(Source::Review(Review { repo, pull, id })),
+        }
+    }
+}
+
+/// Read suggestions from the JSON file at `path`, or from standard input if
+/// `path` is `-`.
+fn read_json(path: &str) -> Result<Vec<Suggestion>, Error> {
+    let json = if path == "-" {
+        let mut json = String::new();
+
+        io::stdin()
+            .read_to_string(&mut json)
+            .map_err(|e| Error::Io {
+                source: e,
+                message: "Unable to read standard input".to_owned(),
+            })?;
+
+        json
+    } else {
+        fs::read_to_string(path)
+            .map_err(|e| Error::Io {
+                source: e,
+                message: format!("Unable to read '{}'", path),
+            })?
+    };
+
+    Ok(Suggestion::list_from_json(&json)?)
+}
+
+/// Fetch all suggestions in `config.suggestions` with a pool of worker
+/// threads, and read the suggestions in `config.json_files`.
+///
+/// The workers share one client per repository. Reviews expand to all of
+/// their suggestions. The results are returned in the same order as
+/// `config.suggestions`, followed by the suggestions from
+/// `config.json_files`. With `config.current`, the pending suggestions on the
+/// pull request for the current branch come first.
+fn fetch_all(config: &Config) -> Vec<Result<Suggestion, Error>> {
+    let clients = Clients::default();
+    let mut o_r = None;
+    let mut results: Vec<Vec<Result<Suggestion, Error>>> = Vec::new();
+    let mut jobs = Vec::new();
+
+    if config.current {
+        results.push(
+            match current_suggestions(config, &clients) {
+                Ok(suggestions) => suggestions.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            }
+        );
+    }
+
+    let offset = results.len();
+
+    for (i, arg) in config.suggestions.iter().enumerate() {
+        let i = i + offset;
+
+        match parse_source(config, &mut o_r, arg) {
+            Ok(Source::Json(suggestions)) =>
+                results.push(suggestions.into_iter().map(Ok).collect()),
+            Ok(source) => {
+                results.push(Vec::new());
+                jobs.push((i, source));
+            },
+            Err(e) => results.push(vec![Err(e)]),
+        }
+    }
+
+    for path in &config.json_files {
+        match read_json(path) {
+            Ok(suggestions) =>
+                results.push(suggestions.into_iter().map(Ok).collect()),
+            Err(e) => results.push(vec![Err(e)]),
+        }
+    }
+
+    run_jobs(jobs, &mut results, |source| match source {
+        Source::Comment(comment) => vec![fetch(config, &clients, comment)],
+        Source::Review(review) =>
+            match fetch_review(config, &clients, review) {
+                Ok(s) => s.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            },
+        Source::Json(suggestions) => suggestions.into_iter().map(Ok).collect(),
+    });
+
+    results.into_iter()
+        .flatten()
+        .filter(|s| match s {
+            Ok(s) => config.filter.matches(s),
+            Err(_) => true,
+        })
+        .collect()
+}
+
+/// Run `f` on each of `jobs`, given as a result index and job, with a pool
+/// of worker threads, and store the output of each job at its index in
+/// `results`.
+fn run_jobs<J, T, F>(jobs: Vec<(usize, J)>, results: &mut [Vec<T>], f: F)
+where
+    J: Send,
+    T: Send,
+    F: Fn(J) -> Vec<T> + Sync,
+{
+    let worker_count = jobs.len().min(MAX_WORKERS);
+    let jobs = Mutex::new(jobs.into_iter());
+    let results = Mutex::new(results);
+
+    thread::scope(|scope| {
+        for _ in 0..worker_count {
+            scope.spawn(|| {
+                loop {
+                    let job = jobs.lock()
+                        .expect("jobs lock is not poisoned")
+                        .next();
+
+                    let (i, job) = match job {
+                        Some(j) => j,
+                        None => break,
+                    };
+
+                    let output = f(job);
+
+                    results.lock()
+                        .expect("results lock is not poisoned")[i] = output;
+                }
+            });
+        }
+    });
+}
+
+/// Get the pending suggestions on the open pull request for the current
+/// branch: those that aren't applied, resolved or outdated.
+fn current_suggestions(
+    config: &Config,
+    clients: &Clients,
+) -> Result<Vec<Suggestion>, Error> {
+    let repo = config.repository()?;
+    let pull_url = current_pull_request_with_repo(config, &repo)?;
+    let mut pending = Vec::new();
+
+    let suggestions =
+        pull_request_suggestions_with_clients(config, clients, &pull_url)?;
+
+    for suggestion in suggestions {
+        match status_with_repo(&suggestion, &repo)? {
+            State::Applies | State::Conflicts => pending.push(suggestion),
+            State::Applied | State::Outdated | State::Resolved => (),
+        }
+    }
+
+    Ok(pending)
+}
+
+/// Fetch `comment` from the first of its candidate repositories that has it,
+/// reusing or adding a client in `clients` for each repository.
+///
+/// Errors other than a missing comment are returned immediately. If none of
+/// the repositories have the comment, return the error from the first one.
+fn fetch(
+    config: &Config,
+    clients: &Clients,
+    comment: Comment,
+) -> Result<Suggestion, Error> {
+    let mut first_error = None;
+
+    for repo in comment.repos {
+        let client = clients.fetcher(config, repo, comment.forge.clone())?;
+
+        match client.fetch(&comment.id) {
+            Ok(s) => return Ok(s),
+            Err(e @ client::Error::NotFound(_)) => {
+                first_error.get_or_insert(e);
+            },
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(
+        first_error
+            .expect("comment has at least one candidate repository")
+            .into()
+    )
+}
+
+/// Fetch the suggestions in `review`, sorted by file and line, reusing or
+/// adding a client in `clients` for its repository.
+fn fetch_review(
+    config: &Config,
+    clients: &Clients,
+    review: Review,
+) -> Result<Vec<Suggestion>, Error> {
+    let client = clients.github(config, &review.repo)?;
+
+    Ok(client.review(&review.pull, &review.id)?)
+}
+
+/// Create a client for pull request `pull` in `repo` on the Gitea server at
+/// `base_url`.
+fn gitea_client(
+    config: &Config,
+    repo: &Repo,
+    base_url: &str,
+    pull: &str,
+) -> Result<gitea::Client, Error> {
+    let token = config.require_gitea_token()?;
+    let api_url = config.api_url(&repo.0, &repo.1, &repo.2);
+
+    let client = match api_url {
+        Some(api_url) => gitea::Client::with_api_url(
+            api_url,
+            token,
+            &repo.1,
+            &repo.2,
+            pull,
+        )?,
+        None => gitea::Client::new(
+            base_url,
+            token,
+            &repo.1,
+            &repo.2,
+            pull,
+        )?,
+    };
+
+    Ok(client)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use github_suggestion::{url, Author, Side};
+
+    fn config(keep_going: bool) -> Config {
+        Config {
+            keep_going,
+            suggestions: vec![
+                "not-a-url".to_owned(),
+                "https://github.com/teddywing/git-suggestion/pull/1".to_owned(),
+            ],
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn for_suggestion_stops_at_first_error() {
+        let result = for_suggestion(&config(false), |_| Ok(()));
+
+        match result {
+            Err(Error::Url(url::Error::Url(_))) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn for_suggestion_keep_going_collects_errors() {
+        let result = for_suggestion(&config(true), |_| Ok(()));
+
+        match result {
+            Err(Error::Multiple(errors)) => {
+                assert_eq!(errors.len(), 2);
+
+                match &errors[1] {
+                    Error::Url(url::Error::NoFragment) => (),
+                    e => panic!("unexpected error: {:?}", e),
+                }
+            },
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn run_jobs_keeps_results_in_job_order() {
+        let mut results = vec![
+            Vec::new(),
+            Vec::new(),
+            vec!["json"],
+            Vec::new(),
+            Vec::new(),
+        ];
+
+        // Later jobs finish first.
+        let jobs = vec![(0, 60), (1, 40), (3, 20), (4, 0)];
+        let finished = Mutex::new(Vec::new());
+
+        run_jobs(jobs, &mut results, |delay| {
+            thread::sleep(std::time::Duration::from_millis(delay));
+            finished.lock().unwrap().push(delay);
+
+            vec!["fetched"; (delay / 20) as usize + 1]
+        });
+
+        assert_eq!(finished.into_inner().unwrap(), vec![0, 20, 40, 60]);
+        assert_eq!(
+            results,
+            vec![
+                vec!["fetched"; 4],
+                vec!["fetched"; 3],
+                vec!["json"],
+                vec!["fetched"; 2],
+                vec!["fetched"; 1],
+            ],
+        );
+    }
+
+    /// A client that fails with `error`, or finds every comment if there is
+    /// none.
+    struct FakeClient {
+        error: Option<fn(String) -> client::Error>,
+    }
+
+    impl Fetch for FakeClient {
+        fn fetch(&self, id: &str) -> Result<Suggestion, client::Error> {
+            match self.error {
+                Some(error) => Err(error(id.to_owned())),
+                None => Ok(Suggestion {
+                    id: id.to_owned(),
+                    review_id: None,
+                    path: "Jabberwocky.txt".to_owned(),
+                    commit: "89840a2".to_owned(),
+                    start_line: 1,
+                    end_line: 1,
+                    side: Side::Right,
+                    message: String::new(),
+                    replacement: String::new(),
+                    author: Author {
+                        name: "oshino-shinobu".to_owned(),
+                        email: String::new(),
+                    },
+                    created_at: "2020-07-23T17:20:29Z".to_owned(),
+                    url: None,
+                    outdated: false,
+                    resolved: None,
+                }),
             }
+        }
+    }
+
+    fn repo(name: &str) -> Repo {
+        ("github.com".to_owned(), "teddywing".to_owned(), name.to_owned())
+    }
+
+    fn comment(repos: &[&str]) -> Comment {
+        Comment {
+            repos: repos.iter().map(|r| repo(r)).collect(),
+            id: "459691747".to_owned(),
+            forge: Forge::Github,
+        }
+    }
+
+    #[test]
+    fn fetch_falls_back_to_next_repo_only_when_not_found() {
+        let fakes = vec![
+            ("missing", FakeClient { error: Some(client::Error::NotFound) }),
+            ("broken", FakeClient { error: Some(client::Error::Github) }),
+            ("found", FakeClient { error: None }),
+        ];
+        let clients = Clients {
+            fetchers: Mutex::new(
+                fakes.into_iter()
+                    .map(|(name, fake)| {
+                        let client: Arc<dyn Fetch> = Arc::new(fake);
+
+                        ((repo(name), Forge::Github), client)
+                    })
+                    .collect(),
+            ),
+            ..Clients::default()
         };
+        let config = Config::default();
+
+        let suggestion =
+            fetch(&config, &clients, comment(&["missing", "found"])).unwrap();
+        assert_eq!(suggestion.id, "459691747");
+
+        match fetch(&config, &clients, comment(&["broken", "found"])) {
+            Err(Error::Client(client::Error::Github(_))) => (),
+            r => panic!("expected the first error, got {:?}", r),
+        }
 
-        f(&suggestion);
+        match fetch(&config, &clients, comment(&["missing", "missing"])) {
+            Err(Error::Client(client::Error::NotFound(_))) => (),
+            r => panic!("expected comment not to be found, got {:?}", r),
+        }
     }
 }